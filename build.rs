@@ -1,4 +1,11 @@
 fn main() {
+    // Nothing to link when the crate is built without MediaInfo, or loads it at runtime.
+    if std::env::var_os("CARGO_FEATURE_MEDIAINFO").is_none()
+        || std::env::var_os("CARGO_FEATURE_MEDIAINFO_DLOPEN").is_some()
+    {
+        return;
+    }
+
     pkg_config::Config::new()
         .statik(true)
         .atleast_version("1.2.13")
@@ -0,0 +1,89 @@
+use crate::{item::Item, job::Job, needs::Needs, tags, Result};
+use serde::{Deserialize, Serialize};
+
+/// What a Job does with the items that pass its conditions. Jobs without actions move their
+/// items.
+///
+/// ```yaml
+/// actions:
+///   - add_tag: processed
+///   - remove_tag: inbox
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    /// Moves the item to the destination built from the Job's pattern.
+    Move,
+    /// Adds a tag to `user.xdg.tags`, so the item can be marked instead of moved.
+    AddTag(#[serde(deserialize_with = "tags::trimmed")] String),
+    RemoveTag(#[serde(deserialize_with = "tags::trimmed")] String),
+    /// Renames a file whose content isn't what its extension says to the extension of its
+    /// content, e.g. `invoice.pdf` to `invoice.exe`.
+    FixExtension,
+}
+
+impl Action {
+    /// Checks the action before any item is processed.
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            Self::AddTag(tag) | Self::RemoveTag(tag) => tags::validate(tag),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn apply(&self, job: &Job, item: &mut Item) -> Result<()> {
+        match self {
+            Self::Move => job.move_item(item),
+            Self::AddTag(tag) => {
+                item.add_tag(tag)?;
+                println!("{}\n\t\t+{tag}", item.path().display());
+
+                Ok(())
+            }
+            Self::RemoveTag(tag) => {
+                item.remove_tag(tag)?;
+                println!("{}\n\t\t-{tag}", item.path().display());
+
+                Ok(())
+            }
+            Self::FixExtension => {
+                if !item.is_mismatched() {
+                    return Ok(());
+                }
+
+                let Some(extension) = item.true_extension() else {
+                    return Ok(());
+                };
+
+                let path = item.path();
+                let dest = path.with_extension(extension);
+
+                if dest.exists() {
+                    eprintln!(
+                        "warning: {} isn't renamed, {} already exists",
+                        path.display(),
+                        dest.display()
+                    );
+
+                    return Ok(());
+                }
+
+                // Printed first, so a rename that fails still shows what was tried.
+                println!("{}\n\t\t{}", path.display(), dest.display());
+
+                item.rename_to(dest)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the action reads the content of items. Moving reads what the pattern needs,
+    /// which the Job works out.
+    pub(crate) fn needs(&self) -> Needs {
+        match self {
+            Self::FixExtension => Needs::CONTENT,
+            _ => Needs::NONE,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use file_format::FileFormat;
+use flate2::read::GzDecoder;
+use liblzma::read::XzDecoder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// What an archive holds, read from the names of its entries without extracting them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ArchiveInfo {
+    /// The number of files, not counting folders.
+    pub(crate) count: usize,
+    /// How many files have each extension, in lowercase. Files without one aren't counted.
+    pub(crate) extensions: BTreeMap<String, usize>,
+    /// The folder everything is in, when it's the only entry at the top level.
+    pub(crate) folder: Option<String>,
+}
+
+impl ArchiveInfo {
+    fn new(entries: impl IntoIterator<Item = (String, bool)>) -> Self {
+        let mut info = Self::default();
+        let mut top_level: Vec<(String, bool)> = Vec::new();
+
+        for (name, is_dir) in entries {
+            let name = name.trim_start_matches("./").trim_matches('/');
+            let Some(first) = name.split('/').next().filter(|first| !first.is_empty()) else {
+                continue;
+            };
+
+            // Anything with a path below the top level is in a folder there.
+            let in_folder = is_dir || name.contains('/');
+
+            match top_level.iter_mut().find(|(n, _)| n == first) {
+                Some((_, folder)) => *folder |= in_folder,
+                None => top_level.push((first.to_string(), in_folder)),
+            }
+
+            if is_dir {
+                continue;
+            }
+
+            info.count += 1;
+
+            if let Some(ext) = Path::new(name).extension() {
+                *info
+                    .extensions
+                    .entry(ext.to_string_lossy().to_lowercase())
+                    .or_default() += 1;
+            }
+        }
+
+        if let [(name, true)] = top_level.as_slice() {
+            info.folder = Some(name.clone());
+        }
+
+        info
+    }
+
+    /// Whether a file in the archive has the extension.
+    pub(crate) fn contains(&self, extension: &str) -> bool {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+
+        self.extensions.contains_key(&extension)
+    }
+}
+
+/// Lists a ZIP, tar (also compressed with gzip or xz) or 7z archive. Other files, and
+/// compressed files that don't hold a tar archive, give `None`.
+pub(crate) fn read(path: &Path, format: FileFormat) -> Option<ArchiveInfo> {
+    let entries = match format {
+        FileFormat::Zip => zip_entries(path)?,
+        FileFormat::TapeArchive => tar_entries(open(path)?)?,
+        FileFormat::Gzip => tar_entries(GzDecoder::new(open(path)?))?,
+        FileFormat::Xz => tar_entries(XzDecoder::new(open(path)?))?,
+        FileFormat::SevenZip => sevenz_rust2::Archive::open(path)
+            .ok()?
+            .files
+            .iter()
+            .map(|entry| (entry.name().to_string(), entry.is_directory()))
+            .collect(),
+        _ => return None,
+    };
+
+    Some(ArchiveInfo::new(entries))
+}
+
+fn open(path: &Path) -> Option<BufReader<File>> {
+    File::open(path).ok().map(BufReader::new)
+}
+
+/// ZIP archives list their entries at the end, so only that part is read.
+fn zip_entries(path: &Path) -> Option<Vec<(String, bool)>> {
+    let archive = zip::ZipArchive::new(open(path)?).ok()?;
+
+    Some(
+        archive
+            .file_names()
+            .map(|name| (name.to_string(), name.ends_with('/')))
+            .collect(),
+    )
+}
+
+/// Tar archives have a header before each entry, so the whole archive is read, skipping the
+/// content.
+fn tar_entries(reader: impl Read) -> Option<Vec<(String, bool)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries().ok()? {
+        let entry = entry.ok()?;
+        let name = entry.path().ok()?.to_string_lossy().to_string();
+
+        entries.push((name, entry.header().entry_type().is_dir()));
+    }
+
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(entries: &[(&str, bool)]) -> ArchiveInfo {
+        ArchiveInfo::new(
+            entries
+                .iter()
+                .map(|(name, is_dir)| (name.to_string(), *is_dir)),
+        )
+    }
+
+    #[test]
+    fn leading_dots_are_ignored() {
+        let info = info(&[
+            ("./", true),
+            ("./photos/", true),
+            ("./photos/a.JPG", false),
+            ("./photos/b.jpg", false),
+        ]);
+
+        assert_eq!(info.count, 2);
+        assert_eq!(info.extensions, BTreeMap::from([("jpg".to_string(), 2)]));
+        assert_eq!(info.folder.as_deref(), Some("photos"));
+        assert!(info.contains(".JPG"));
+        assert!(!info.contains("png"));
+    }
+
+    #[test]
+    fn folders_are_not_counted() {
+        let info = info(&[("backup/", true), ("backup/old/", true)]);
+
+        assert_eq!(info.count, 0);
+        assert!(info.extensions.is_empty());
+        assert_eq!(info.folder.as_deref(), Some("backup"));
+    }
+
+    #[test]
+    fn a_single_file_is_not_a_folder() {
+        let info = info(&[("report.pdf", false)]);
+
+        assert_eq!(info.count, 1);
+        assert_eq!(info.folder, None);
+    }
+
+    #[test]
+    fn nested_paths_share_their_root() {
+        // Tar archives don't always list the folders themselves.
+        let info = info(&[
+            ("project/src/main.rs", false),
+            ("project/README", false),
+            ("project/src/lib/mod.rs", false),
+        ]);
+
+        assert_eq!(info.count, 3);
+        assert_eq!(info.extensions, BTreeMap::from([("rs".to_string(), 2)]));
+        assert_eq!(info.folder.as_deref(), Some("project"));
+    }
+
+    #[test]
+    fn several_top_level_entries_have_no_folder() {
+        let info = info(&[
+            ("project/", true),
+            ("project/a.txt", false),
+            ("b.txt", false),
+        ]);
+
+        assert_eq!(info.count, 2);
+        assert_eq!(info.folder, None);
+    }
+
+    #[test]
+    fn empty_archives_have_nothing() {
+        assert_eq!(info(&[]), ArchiveInfo::default());
+        assert_eq!(info(&[("./", true)]), ArchiveInfo::default());
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use lofty::{
+    prelude::*,
+    tag::{ItemKey, Tag},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The tags of an audio file that can be used in a pattern.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct AudioTags {
+    pub(crate) artist: Option<String>,
+    pub(crate) album_artist: Option<String>,
+    pub(crate) album: Option<String>,
+    pub(crate) track: Option<u32>,
+    pub(crate) disc: Option<u32>,
+    pub(crate) genre: Option<String>,
+    pub(crate) date: Option<NaiveDateTime>,
+}
+
+impl AudioTags {
+    /// Reads ID3v2, Vorbis comments (Ogg and FLAC), MP4 atoms and the other tag formats lofty
+    /// supports, preferring the primary tag of the format.
+    pub(crate) fn read(path: &Path) -> Option<Self> {
+        let file = lofty::read_from_path(path).ok()?;
+        let tag = file.primary_tag().or_else(|| file.first_tag())?;
+        let artist = text(tag.artist());
+
+        Some(Self {
+            album_artist: tag
+                .get_string(ItemKey::AlbumArtist)
+                .and_then(|a| text(Some(a.into())))
+                .or_else(|| artist.clone()),
+            artist,
+            album: text(tag.album()),
+            track: tag.track(),
+            disc: tag.disk(),
+            genre: text(tag.genre()),
+            date: date(tag),
+        })
+    }
+}
+
+fn text(value: Option<std::borrow::Cow<'_, str>>) -> Option<String> {
+    let value = value?;
+    let value = value.trim();
+
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// The recording date, or the year when that's all the tag has.
+fn date(tag: &Tag) -> Option<NaiveDateTime> {
+    let (year, month, day) = match tag.date() {
+        Some(date) => (
+            date.year.into(),
+            date.month.unwrap_or(1).into(),
+            date.day.unwrap_or(1).into(),
+        ),
+        None => (
+            tag.get_string(ItemKey::Year)?
+                .trim()
+                .get(..4)?
+                .parse()
+                .ok()?,
+            1,
+            1,
+        ),
+    };
+
+    NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(0, 0, 0)
+}
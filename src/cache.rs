@@ -0,0 +1,435 @@
+use crate::{
+    archive::ArchiveInfo, audio::AudioTags, directory::Summary, document::DocumentInfo,
+    email::EmailInfo, mediainfo::MediaReport, photo::PhotoInfo, Result,
+};
+use file_format::FileFormat;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    env,
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// The version of the cache file. Entries written by other versions are dropped.
+///
+/// 2: formats are told apart by their content, e.g. DOCX from ZIP.
+/// 3: dimensions are as shown, after the rotation of photos and videos.
+/// 4: the `extra` fields of MediaInfo reports are kept as `extra.Name`.
+const VERSION: u32 = 4;
+
+/// Identifies a file, and the version of its content by its size and modification time.
+/// Directories are identified the same way, so adding or removing an entry changes them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileId {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+impl FileId {
+    pub(crate) fn new(meta: &Metadata) -> Self {
+        Self {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            size: meta.size(),
+            mtime: meta.mtime(),
+            mtime_nsec: meta.mtime_nsec(),
+        }
+    }
+
+    /// The key of the file in the cache. A modified file keeps its key, so its new entry
+    /// replaces the old one.
+    fn key(&self) -> String {
+        format!("{}:{}", self.dev, self.ino)
+    }
+}
+
+/// The metadata read from the content of a file, which is what the cache keeps.
+///
+/// Each field is `None` until it's read, and `Some(None)` when the file doesn't have it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Extracted {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) format: Option<Option<FileFormat>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) dimensions: Option<Option<(usize, usize)>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) photo: Option<Option<PhotoInfo>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) audio: Option<Option<AudioTags>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) media_report: Option<Option<MediaReport>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) document: Option<Option<DocumentInfo>>,
+    /// What a directory contains, kept for its dominant kind.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) directory: Option<Option<Summary>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) archive: Option<Option<ArchiveInfo>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) email: Option<Option<EmailInfo>>,
+    /// The number of messages in an mbox file.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) messages: Option<Option<usize>>,
+    /// Fields read with MediaInfo, by `Stream/index/Field`, and stream counts by
+    /// `Stream/count`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) media: BTreeMap<String, String>,
+}
+
+/// Reads a field that's present in the file as read, even when it's `null`.
+fn read<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// A cached file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    path: PathBuf,
+    id: FileId,
+    metadata: Extracted,
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The kinds of metadata cached for the file.
+    pub fn contents(&self) -> Vec<&'static str> {
+        let metadata = &self.metadata;
+
+        [
+            ("format", metadata.format.is_some()),
+            ("dimensions", metadata.dimensions.is_some()),
+            ("photo", metadata.photo.is_some()),
+            ("audio", metadata.audio.is_some()),
+            ("media report", metadata.media_report.is_some()),
+            ("document", metadata.document.is_some()),
+            ("directory", metadata.directory.is_some()),
+            ("archive", metadata.archive.is_some()),
+            ("email", metadata.email.is_some()),
+            ("messages", metadata.messages.is_some()),
+            ("media", !metadata.media.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, cached)| cached.then_some(name))
+        .collect()
+    }
+
+    /// Whether the file is still there and unchanged.
+    fn is_current(&self) -> bool {
+        fs::metadata(&self.path).is_ok_and(|meta| FileId::new(&meta) == self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CacheFile<'c> {
+    version: u32,
+    entries: Cow<'c, BTreeMap<String, Entry>>,
+}
+
+/// The metadata read from files by earlier runs, so unchanged files don't have to be read
+/// again. It's kept in a single file in the user's cache directory.
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+    changed: bool,
+}
+
+impl Cache {
+    /// The cache file, in `$XDG_CACHE_HOME` or `~/.cache`.
+    pub fn location() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+        Some(dir.join("hazel-rs").join("metadata.json"))
+    }
+
+    /// Opens the cache. A missing or unreadable cache file starts an empty cache, which
+    /// replaces it when it's saved.
+    pub fn open() -> Option<Self> {
+        Some(Self::open_at(Self::location()?))
+    }
+
+    fn open_at(path: PathBuf) -> Self {
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|file| serde_json::from_slice::<CacheFile>(&file).ok())
+            .filter(|file| file.version == VERSION)
+            .map(|file| file.entries.into_owned())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Writes the cache file if any entry changed.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let json = serde_json::to_vec(&CacheFile {
+            version: VERSION,
+            entries: Cow::Borrowed(&self.entries),
+        })
+        .map_err(std::io::Error::from)?;
+
+        // Written next to the cache and moved over it, so an interrupted run can't leave
+        // half a file behind.
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, json)?;
+        fs::rename(&temp, &self.path)?;
+        self.changed = false;
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes the entries of files that were deleted or changed since they were cached,
+    /// returning how many were removed.
+    pub fn prune(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.is_current());
+
+        let removed = before - self.entries.len();
+        self.changed |= removed > 0;
+
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.changed |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    /// The metadata cached for a file, if it hasn't changed since.
+    pub(crate) fn get(&self, id: &FileId) -> Option<&Extracted> {
+        self.entries
+            .get(&id.key())
+            .filter(|entry| entry.id == *id)
+            .map(|entry| &entry.metadata)
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, id: FileId, metadata: Extracted) {
+        self.entries.insert(id.key(), Entry { path, id, metadata });
+        self.changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, SystemTime},
+    };
+
+    /// A directory of its own for each test, as they run at the same time.
+    fn temp_dir() -> PathBuf {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir().join(format!(
+            "hazel-cache-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn id(path: &Path) -> FileId {
+        FileId::new(&fs::metadata(path).unwrap())
+    }
+
+    fn extracted() -> Extracted {
+        Extracted {
+            format: Some(None),
+            dimensions: Some(Some((3, 4))),
+            messages: Some(Some(2)),
+            ..Extracted::default()
+        }
+    }
+
+    #[test]
+    fn changed_files_are_stale() {
+        let dir = temp_dir();
+        let file = dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut cache = Cache::open_at(dir.join("cache.json"));
+        cache.insert(file.clone(), id(&file), extracted());
+        assert!(cache.get(&id(&file)).is_some());
+
+        // The file keeps its key, but not its size or modification time.
+        fs::write(&file, "ab").unwrap();
+        assert_eq!(id(&file).key(), cache.entries().next().unwrap().id.key());
+        assert!(cache.get(&id(&file)).is_none());
+
+        cache.insert(file.clone(), id(&file), extracted());
+        let modified = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(cache.get(&id(&file)).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn metadata_is_read_back_as_cached() {
+        let dir = temp_dir();
+        let file = dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut cache = Cache::open_at(dir.join("cache.json"));
+        cache.insert(file.clone(), id(&file), extracted());
+        cache.save().unwrap();
+
+        let cache = Cache::open_at(dir.join("cache.json"));
+        let metadata = cache.get(&id(&file)).unwrap();
+
+        // A format that was read and isn't known stays apart from one that wasn't read.
+        assert_eq!(metadata.format, Some(None));
+        assert_eq!(metadata.dimensions, Some(Some((3, 4))));
+        assert_eq!(metadata.messages, Some(Some(2)));
+        assert!(metadata.photo.is_none());
+        assert!(metadata.email.is_none());
+        assert_eq!(
+            cache.entries().next().unwrap().contents(),
+            ["format", "dimensions", "messages"]
+        );
+    }
+
+    #[test]
+    fn other_versions_are_dropped() {
+        let dir = temp_dir();
+        let file = dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut cache = Cache::open_at(dir.join("cache.json"));
+        cache.insert(file.clone(), id(&file), extracted());
+        cache.save().unwrap();
+
+        let json = fs::read_to_string(dir.join("cache.json")).unwrap();
+        let old = json.replace(
+            &format!("\"version\":{VERSION}"),
+            &format!("\"version\":{}", VERSION - 1),
+        );
+        assert_ne!(json, old);
+        fs::write(dir.join("cache.json"), old).unwrap();
+
+        assert!(Cache::open_at(dir.join("cache.json")).is_empty());
+    }
+
+    #[test]
+    fn prune_removes_deleted_and_changed_files() {
+        let dir = temp_dir();
+        let [kept, deleted, changed] = ["kept", "deleted", "changed"].map(|name| dir.join(name));
+        let mut cache = Cache::open_at(dir.join("cache.json"));
+
+        for file in [&kept, &deleted, &changed] {
+            fs::write(file, "a").unwrap();
+            cache.insert(file.clone(), id(file), extracted());
+        }
+
+        fs::remove_file(&deleted).unwrap();
+        fs::write(&changed, "ab").unwrap();
+
+        assert_eq!(cache.prune(), 2);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&id(&kept)).is_some());
+        assert_eq!(cache.prune(), 0);
+    }
+
+    #[test]
+    fn unchanged_caches_are_not_written() {
+        let dir = temp_dir();
+        let mut cache = Cache::open_at(dir.join("cache.json"));
+
+        cache.save().unwrap();
+        assert!(!dir.join("cache.json").exists());
+
+        cache.clear();
+        cache.save().unwrap();
+        assert!(!dir.join("cache.json").exists());
+    }
+}
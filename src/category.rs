@@ -0,0 +1,372 @@
+use crate::{error::AppError, options::Category, Result};
+use file_format::{FileFormat, Kind};
+use regex::Regex;
+
+/// Every kind, so names in categories can be looked up.
+const KINDS: [Kind; 19] = [
+    Kind::Application,
+    Kind::Audio,
+    Kind::Font,
+    Kind::Image,
+    Kind::Model,
+    Kind::Text,
+    Kind::Video,
+    Kind::Archive,
+    Kind::Book,
+    Kind::Certificate,
+    Kind::Compression,
+    Kind::Disk,
+    Kind::Document,
+    Kind::Executable,
+    Kind::Geospatial,
+    Kind::Package,
+    Kind::Playlist,
+    Kind::Rom,
+    Kind::Subtitle,
+];
+
+/// The name of a kind of file.
+pub(crate) fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Application => "application",
+        Kind::Audio => "audio",
+        Kind::Font => "font",
+        Kind::Image => "image",
+        Kind::Model => "model",
+        Kind::Text => "text",
+        Kind::Video => "video",
+        Kind::Archive => "archive",
+        Kind::Book => "book",
+        Kind::Certificate => "certificate",
+        Kind::Compression => "compression",
+        Kind::Disk => "disk",
+        Kind::Document => "document",
+        Kind::Executable => "executable",
+        Kind::Geospatial => "geospatial",
+        Kind::Package => "package",
+        Kind::Playlist => "playlist",
+        Kind::Rom => "rom",
+        Kind::Subtitle => "subtitle",
+    }
+}
+
+/// The kind of a file going by its extension, for files whose content can't be read, like the
+/// ones in an archive. Extensions follow the formats the content is detected as, and the rest
+/// go by the type of their mime type.
+pub(crate) fn extension_kind(extension: &str) -> Kind {
+    match extension {
+        "7z" | "cab" | "cpio" | "rar" | "tar" | "zip" => Kind::Archive,
+        "azw3" | "epub" | "fb2" | "mobi" => Kind::Book,
+        "cer" | "crt" | "der" | "p12" | "pem" | "pfx" => Kind::Certificate,
+        "bz2" | "gz" | "lz" | "lz4" | "xz" | "zst" => Kind::Compression,
+        "dmg" | "iso" | "qcow2" | "vhd" | "vmdk" => Kind::Disk,
+        "doc" | "docx" | "odp" | "ods" | "odt" | "pdf" | "ppt" | "pptx" | "rtf" | "xls"
+        | "xlsx" => Kind::Document,
+        "class" | "dll" | "dylib" | "elf" | "exe" | "so" | "wasm" => Kind::Executable,
+        "otf" | "ttf" | "woff" | "woff2" => Kind::Font,
+        "geojson" | "gpx" | "kml" | "shp" => Kind::Geospatial,
+        "glb" | "gltf" | "obj" | "stl" => Kind::Model,
+        "apk" | "crx" | "deb" | "jar" | "msi" | "rpm" | "xpi" => Kind::Package,
+        "m3u" | "m3u8" | "pls" => Kind::Playlist,
+        "gb" | "gba" | "nes" | "sfc" => Kind::Rom,
+        "ass" | "srt" | "ssa" | "sub" | "vtt" => Kind::Subtitle,
+        extension => match mime_guess::from_ext(extension).first() {
+            Some(mime) => match mime.type_().as_str() {
+                "audio" => Kind::Audio,
+                "font" => Kind::Font,
+                "image" => Kind::Image,
+                "model" => Kind::Model,
+                "text" => Kind::Text,
+                "video" => Kind::Video,
+                _ => Kind::Application,
+            },
+            None => Kind::Application,
+        },
+    }
+}
+
+fn parse_kind(name: &str) -> Result<Kind> {
+    KINDS
+        .into_iter()
+        .find(|kind| kind_name(*kind).eq_ignore_ascii_case(name))
+        .ok_or_else(|| AppError::InvalidKind {
+            kind: name.to_string(),
+        })
+}
+
+/// What's known about an item when it's put in a category. Directories only have the kind
+/// most of their files are.
+#[derive(Debug, Default)]
+pub(crate) struct Facts<'f> {
+    pub(crate) kind: Option<Kind>,
+    /// The extension in lowercase.
+    pub(crate) extension: Option<&'f str>,
+    pub(crate) mime: Option<&'f str>,
+    pub(crate) format: Option<FileFormat>,
+}
+
+/// The categories of a Job, in the order they're tried.
+#[derive(Debug, Default)]
+pub(crate) struct Categories(Vec<Matcher>);
+
+#[derive(Debug)]
+struct Matcher {
+    name: String,
+    kinds: Vec<Kind>,
+    extensions: Vec<String>,
+    mimes: Vec<Regex>,
+    formats: Vec<String>,
+}
+
+impl Categories {
+    pub(crate) fn new(categories: &[Category]) -> Result<Self> {
+        let matchers = categories
+            .iter()
+            .map(|category| {
+                Ok(Matcher {
+                    name: category.name.clone(),
+                    kinds: category
+                        .kinds
+                        .iter()
+                        .map(|kind| parse_kind(kind))
+                        .collect::<Result<_>>()?,
+                    extensions: category
+                        .extensions
+                        .iter()
+                        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                        .collect(),
+                    mimes: category.mimes.iter().map(|glob| mime_glob(glob)).collect(),
+                    formats: category.formats.iter().map(|f| f.to_lowercase()).collect(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self(matchers))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The first category the item is in.
+    pub(crate) fn find(&self, facts: &Facts) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|matcher| matcher.matches(facts))
+            .map(|matcher| matcher.name.as_str())
+    }
+}
+
+impl Matcher {
+    /// An item is in the category when any of its kinds, extensions, mime types or formats
+    /// match.
+    fn matches(&self, facts: &Facts) -> bool {
+        facts.kind.is_some_and(|kind| self.kinds.contains(&kind))
+            || facts
+                .extension
+                .is_some_and(|ext| self.extensions.iter().any(|e| e == ext))
+            || facts
+                .mime
+                .is_some_and(|mime| self.mimes.iter().any(|glob| glob.is_match(mime)))
+            || facts.format.is_some_and(|format| {
+                let names = [
+                    Some(format.name().to_lowercase()),
+                    format.short_name().map(str::to_lowercase),
+                    Some(format!("{format:?}").to_lowercase()),
+                ];
+
+                self.formats
+                    .iter()
+                    .any(|f| names.iter().flatten().any(|name| name == f))
+            })
+    }
+}
+
+/// Turns a mime glob like `image/*` or `application/vnd.ms-*` into a regex.
+fn mime_glob(glob: &str) -> Regex {
+    let pattern = glob
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+
+    // Escaped text joined by `.*` is always a valid regex.
+    Regex::new(&format!("(?i)^{pattern}$")).expect("mime glob regex")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn categories(categories: Vec<Category>) -> Categories {
+        Categories::new(&categories).unwrap()
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn extensions_have_the_kind_of_their_format() {
+        let kinds = [
+            ("exe", "executable"),
+            ("docx", "document"),
+            ("pdf", "document"),
+            ("epub", "book"),
+            ("gz", "compression"),
+            ("zip", "archive"),
+            ("woff", "font"),
+            ("srt", "subtitle"),
+            ("jpg", "image"),
+            ("mkv", "video"),
+            ("flac", "audio"),
+            ("txt", "text"),
+            ("json", "application"),
+            ("unknown", "application"),
+        ];
+
+        for (extension, name) in kinds {
+            assert_eq!(kind_name(extension_kind(extension)), name, "{extension}");
+        }
+    }
+
+    #[test]
+    fn kinds_are_parsed_by_name() {
+        for kind in KINDS {
+            assert_eq!(parse_kind(kind_name(kind)).ok(), Some(kind));
+        }
+
+        assert_eq!(parse_kind("Video").ok(), Some(Kind::Video));
+        assert!(parse_kind("videos").is_err());
+    }
+
+    #[test]
+    fn the_first_matching_category_wins() {
+        let categories = categories(vec![
+            Category {
+                name: "raw-photos".to_string(),
+                extensions: strings(&["cr2", "nef"]),
+                ..Default::default()
+            },
+            Category {
+                name: "images".to_string(),
+                kinds: strings(&["image"]),
+                ..Default::default()
+            },
+            Category {
+                name: "everything".to_string(),
+                mimes: strings(&["*"]),
+                ..Default::default()
+            },
+        ]);
+        let facts = |extension, mime| Facts {
+            kind: Some(Kind::Image),
+            extension: Some(extension),
+            mime: Some(mime),
+            format: None,
+        };
+
+        assert_eq!(
+            categories.find(&facts("nef", "image/x-nikon-nef")),
+            Some("raw-photos")
+        );
+        assert_eq!(categories.find(&facts("png", "image/png")), Some("images"));
+        assert_eq!(
+            categories.find(&Facts {
+                mime: Some("text/plain"),
+                ..Default::default()
+            }),
+            Some("everything")
+        );
+        assert_eq!(categories.find(&Facts::default()), None);
+    }
+
+    #[test]
+    fn mime_globs_match_whole_types() {
+        let image = mime_glob("image/*");
+        let office = mime_glob("application/vnd.ms-*");
+        let exact = mime_glob("text/plain");
+
+        assert!(image.is_match("image/png"));
+        assert!(image.is_match("IMAGE/JPEG"));
+        assert!(!image.is_match("video/image"));
+        assert!(office.is_match("application/vnd.ms-excel"));
+        assert!(!office.is_match("application/vnd.msword"));
+        assert!(exact.is_match("text/plain"));
+        assert!(!exact.is_match("text/plain+x"));
+        assert!(!exact.is_match("text/plainxtext"));
+        // Only `*` is special, the rest is literal.
+        assert!(!mime_glob("text/x.c").is_match("text/xyc"));
+    }
+
+    #[test]
+    fn extensions_are_normalised() {
+        let categories = categories(vec![Category {
+            name: "photos".to_string(),
+            extensions: strings(&[".JPG", "Jpeg", "png"]),
+            ..Default::default()
+        }]);
+
+        for extension in ["jpg", "jpeg", "png"] {
+            let facts = Facts {
+                extension: Some(extension),
+                ..Default::default()
+            };
+
+            assert_eq!(categories.find(&facts), Some("photos"), "{extension}");
+        }
+
+        let facts = Facts {
+            extension: Some("gif"),
+            ..Default::default()
+        };
+
+        assert_eq!(categories.find(&facts), None);
+    }
+
+    #[test]
+    fn formats_are_matched_by_any_name() {
+        let facts = Facts {
+            format: Some(FileFormat::PortableNetworkGraphics),
+            ..Default::default()
+        };
+
+        for name in [
+            "Portable Network Graphics",
+            "portable network graphics",
+            "PNG",
+            "png",
+            "PortableNetworkGraphics",
+        ] {
+            let categories = categories(vec![Category {
+                name: "png".to_string(),
+                formats: strings(&[name]),
+                ..Default::default()
+            }]);
+
+            assert!(categories.0[0].matches(&facts), "{name}");
+        }
+
+        let categories = categories(vec![Category {
+            name: "jpeg".to_string(),
+            formats: strings(&["JPEG"]),
+            ..Default::default()
+        }]);
+
+        assert!(!categories.0[0].matches(&facts));
+    }
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        let category = Category {
+            name: "pictures".to_string(),
+            kinds: strings(&["picture"]),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            Categories::new(&[category]),
+            Err(AppError::InvalidKind { .. })
+        ));
+    }
+}
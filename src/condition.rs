@@ -0,0 +1,114 @@
+use crate::{error::AppError, item::Item, needs::Needs, search::Search, tags, Result};
+use serde::{Deserialize, Serialize};
+
+/// A check an item has to pass for the Job's actions to run on it.
+///
+/// ```yaml
+/// conditions:
+///   - tagged: inbox
+///   - not:
+///       tagged: processed
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Condition {
+    /// The item has the tag, in `user.xdg.tags`.
+    Tagged(#[serde(deserialize_with = "tags::trimmed")] String),
+    /// The item has the `user.*` extended attribute.
+    Xattr(String),
+    /// A subtitle stream of the video is forced.
+    ForcedSubtitles,
+    /// The item is a file with an executable bit set.
+    Executable,
+    /// Anyone can write to the item.
+    WorldWritable,
+    /// The item is owned by the uid.
+    Owner(u32),
+    /// The item belongs to the gid.
+    Group(u32),
+    /// The content of the file isn't what its extension says.
+    Mismatch,
+    /// The archive has a file with the extension.
+    ArchiveContains(String),
+    /// Everything in the archive is in one folder.
+    SingleFolder,
+    /// The text of a plain text file, PDF, DOCX or ODT has a match.
+    Contains(Search),
+    /// The condition doesn't hold.
+    Not(Box<Condition>),
+    /// At least one of the conditions holds.
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    /// Checks the condition before any item is processed.
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            Self::Tagged(tag) => tags::validate(tag),
+            Self::Xattr(name) if !name.starts_with("user.") => Err(AppError::InvalidAttribute {
+                name: name.to_string(),
+            }),
+            Self::Not(condition) => condition.validate(),
+            Self::Any(conditions) => conditions.iter().try_for_each(Condition::validate),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a condition of the Job itself. Only the match of a `contains` condition here is
+    /// kept for `{match}`, as one inside `not` or `any` isn't what lets the item through.
+    pub(crate) fn holds(&self, item: &mut Item) -> bool {
+        match self {
+            Self::Contains(search) => match item.search(search) {
+                Some(captures) => {
+                    item.keep_captures(captures);
+                    true
+                }
+                None => false,
+            },
+            condition => condition.matches(item),
+        }
+    }
+
+    fn matches(&self, item: &mut Item) -> bool {
+        match self {
+            Self::Tagged(tag) => item.tags().contains(tag),
+            Self::Xattr(name) => item.has_attribute(name),
+            Self::ForcedSubtitles => item.has_forced_subtitle().unwrap_or_default(),
+            Self::Executable => item.is_executable().unwrap_or_default(),
+            Self::WorldWritable => item.is_world_writable().unwrap_or_default(),
+            Self::Owner(uid) => item.owner().is_ok_and(|owner| owner == *uid),
+            Self::Group(gid) => item.group().is_ok_and(|group| group == *gid),
+            Self::Mismatch => item.is_mismatched(),
+            Self::ArchiveContains(extension) => {
+                item.archive().is_some_and(|info| info.contains(extension))
+            }
+            Self::SingleFolder => item.archive().is_some_and(|info| info.folder.is_some()),
+            Self::Contains(search) => item.search(search).is_some(),
+            Self::Not(condition) => !condition.matches(item),
+            Self::Any(conditions) => conditions.iter().any(|c| c.matches(item)),
+        }
+    }
+
+    /// Whether the condition reads the content of items.
+    pub(crate) fn needs(&self) -> Needs {
+        match self {
+            // Extended attributes can change without the file being modified, so they're read
+            // every time, like the file system metadata.
+            Self::Tagged(_)
+            | Self::Xattr(_)
+            | Self::Executable
+            | Self::WorldWritable
+            | Self::Owner(_)
+            | Self::Group(_) => Needs::NONE,
+            Self::ForcedSubtitles
+            | Self::Mismatch
+            | Self::Contains(_)
+            | Self::ArchiveContains(_)
+            | Self::SingleFolder => Needs::CONTENT,
+            Self::Not(condition) => condition.needs(),
+            Self::Any(conditions) => conditions
+                .iter()
+                .fold(Needs::NONE, |needs, c| needs | c.needs()),
+        }
+    }
+}
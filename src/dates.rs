@@ -0,0 +1,284 @@
+use crate::{error::AppError, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+
+/// Date formats commonly found in file names, e.g. `IMG_20230301_123456.jpg`,
+/// `Screenshot 2023-03-01 at 10.00.00.png` or `2023-03-01 invoice.pdf`. Screenshots on a
+/// 12-hour clock end their time with `AM` or `PM`.
+const BUILTIN_FORMATS: [&str; 2] = [
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})-(?P<month>\d{2})-(?P<day>\d{2}) at (?P<hour>\d{1,2})\.(?P<minute>\d{2})\.(?P<second>\d{2})(?:\s?(?P<meridiem>[AP]M))?(?:\D|$)",
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})[-_.]?(?P<month>0[1-9]|1[0-2])[-_.]?(?P<day>0[1-9]|[12]\d|3[01])(?:[-_ T.]?(?P<hour>[01]\d|2[0-3])[-_.:]?(?P<minute>[0-5]\d)[-_.:]?(?P<second>[0-5]\d))?(?:\D|$)",
+];
+
+/// Groups a date format needs to have.
+const REQUIRED_GROUPS: [&str; 3] = ["year", "month", "day"];
+
+/// Reads dates from file names.
+#[derive(Debug)]
+pub(crate) struct FilenameDates {
+    formats: Vec<Regex>,
+}
+
+impl FilenameDates {
+    /// Compiles the custom formats, which are tried before the built-in ones.
+    pub(crate) fn new(custom: &[String]) -> Result<Self> {
+        let mut formats = Vec::new();
+
+        for format in custom {
+            let regex = Regex::new(format)?;
+
+            if REQUIRED_GROUPS
+                .iter()
+                .any(|group| !regex.capture_names().any(|name| name == Some(group)))
+            {
+                return Err(AppError::InvalidDateRegex {
+                    format: format.to_string(),
+                });
+            }
+
+            formats.push(regex);
+        }
+
+        for format in BUILTIN_FORMATS {
+            formats.push(Regex::new(format)?);
+        }
+
+        Ok(Self { formats })
+    }
+
+    /// Finds the first valid date in a file name.
+    pub(crate) fn parse(&self, name: &str) -> Option<NaiveDateTime> {
+        self.formats.iter().find_map(|format| {
+            let captures = format.captures(name)?;
+            let field = |group: &str| -> Option<u32> {
+                match captures.name(group) {
+                    Some(value) => value.as_str().parse().ok(),
+                    None => Some(0),
+                }
+            };
+
+            let year = captures.name("year")?.as_str().parse().ok()?;
+            let hour = match captures.name("meridiem").map(|m| m.as_str()) {
+                Some("AM") if field("hour")? == 12 => 0,
+                Some("PM") if field("hour")? < 12 => field("hour")? + 12,
+                _ => field("hour")?,
+            };
+
+            NaiveDate::from_ymd_opt(year, field("month")?, field("day")?)?.and_hms_opt(
+                hour,
+                field("minute")?,
+                field("second")?,
+            )
+        })
+    }
+}
+
+/// The timezone file system dates are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Timezone {
+    /// The timezone of the system, with the offset it had at each date.
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// Reads `local`, `utc` or an offset from UTC like `+02:00`, `-0530` or `+09`.
+    pub(crate) fn new(timezone: &str) -> Result<Self> {
+        let invalid = || AppError::InvalidTimezone {
+            timezone: timezone.to_string(),
+        };
+
+        match timezone.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "utc" | "z" => Ok(Self::Fixed(FixedOffset::east_opt(0).ok_or_else(invalid)?)),
+            offset => parse_offset(offset).map(Self::Fixed).ok_or_else(invalid),
+        }
+    }
+
+    /// The date and time in this timezone.
+    pub(crate) fn naive(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => time.with_timezone(&Local).naive_local(),
+            Self::Fixed(offset) => time.with_timezone(offset).naive_local(),
+        }
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match offset.as_bytes().first()? {
+        b'+' => (1, offset[1..].replace(':', "")),
+        b'-' => (-1, offset[1..].replace(':', "")),
+        _ => return None,
+    };
+
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().unwrap_or_default();
+
+    if minutes >= 60 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 60 + minutes) * 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(s: &str) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").ok()
+    }
+
+    fn parse(name: &str) -> Option<NaiveDateTime> {
+        FilenameDates::new(&[]).unwrap().parse(name)
+    }
+
+    #[test]
+    fn camera_names() {
+        let taken = date("2023-03-01 12:34:56");
+
+        assert_eq!(parse("IMG_20230301_123456.jpg"), taken);
+        assert_eq!(parse("VID_20230301_123456.mp4"), taken);
+        assert_eq!(parse("20230301_123456.jpg"), taken);
+        assert_eq!(parse("signal-2023-03-01-12-34-56.jpg"), taken);
+        assert_eq!(parse("Photo 2023-03-01T12:34:56.heic"), taken);
+    }
+
+    #[test]
+    fn screenshot_names() {
+        assert_eq!(
+            parse("Screenshot 2023-03-01 at 10.05.09.png"),
+            date("2023-03-01 10:05:09")
+        );
+        assert_eq!(
+            parse("Screen Shot 2023-03-01 at 9.05.09 PM.png"),
+            date("2023-03-01 21:05:09")
+        );
+        assert_eq!(
+            parse("Screenshot 2023-03-01 at 12.05.09\u{202f}AM.png"),
+            date("2023-03-01 00:05:09")
+        );
+    }
+
+    #[test]
+    fn dates_without_a_time() {
+        let day = date("2023-03-01 00:00:00");
+
+        assert_eq!(parse("2023-03-01 invoice.pdf"), day);
+        assert_eq!(parse("invoice_2023_03_01.pdf"), day);
+        assert_eq!(parse("scan.2023.03.01.tiff"), day);
+        assert_eq!(parse("20230301.pdf"), day);
+        // Digits after the date that aren't a time are left alone.
+        assert_eq!(parse("PXL_20230301_123456789.jpg"), day);
+    }
+
+    #[test]
+    fn names_without_a_date() {
+        assert_eq!(parse("notes.txt"), None);
+        assert_eq!(parse("report-2023.pdf"), None);
+        assert_eq!(parse("order 120230301.pdf"), None);
+        assert_eq!(parse("IMG_1234.jpg"), None);
+        assert_eq!(parse("18500301.jpg"), None);
+        assert_eq!(parse("20231301.jpg"), None);
+        assert_eq!(parse("2023-02-30 invoice.pdf"), None);
+    }
+
+    #[test]
+    fn custom_formats_come_first() {
+        let dates = FilenameDates::new(&[
+            r"photo-(?P<day>\d{2})\.(?P<month>\d{2})\.(?P<year>\d{4})".to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            dates.parse("photo-01.03.2023 20220101.jpg"),
+            date("2023-03-01 00:00:00")
+        );
+        assert_eq!(dates.parse("20220101.jpg"), date("2022-01-01 00:00:00"));
+    }
+
+    #[test]
+    fn custom_formats_need_a_year_month_and_day() {
+        assert!(FilenameDates::new(&[r"(?P<year>\d{4})-(?P<month>\d{2})".to_string()]).is_err());
+        assert!(FilenameDates::new(&["(".to_string()]).is_err());
+    }
+
+    fn offset(seconds: i32) -> Option<Timezone> {
+        FixedOffset::east_opt(seconds).map(Timezone::Fixed)
+    }
+
+    #[test]
+    fn timezone_offsets() {
+        assert_eq!(Timezone::new("+02:00").ok(), offset(2 * 3600));
+        assert_eq!(Timezone::new("+0200").ok(), offset(2 * 3600));
+        assert_eq!(Timezone::new("+09").ok(), offset(9 * 3600));
+        assert_eq!(Timezone::new("-0530").ok(), offset(-(5 * 3600 + 30 * 60)));
+        assert_eq!(Timezone::new("-05:30").ok(), offset(-(5 * 3600 + 30 * 60)));
+        assert_eq!(Timezone::new("UTC").ok(), offset(0));
+        assert_eq!(Timezone::new("Z").ok(), offset(0));
+        assert_eq!(Timezone::new("z").ok(), offset(0));
+        assert_eq!(Timezone::new("local").ok(), Some(Timezone::Local));
+        assert_eq!(Timezone::new("Local").ok(), Some(Timezone::Local));
+    }
+
+    #[test]
+    fn invalid_timezones() {
+        for timezone in [
+            "",
+            "02:00",
+            "+2",
+            "+200",
+            "+02:0",
+            "+02:60",
+            "+24:00",
+            "+99",
+            "-25",
+            "+0a:00",
+            "+02:00:00",
+            "Europe/Paris",
+            "utc+1",
+        ] {
+            assert!(
+                matches!(
+                    Timezone::new(timezone),
+                    Err(AppError::InvalidTimezone { .. })
+                ),
+                "{timezone}"
+            );
+        }
+    }
+
+    #[test]
+    fn dates_move_across_midnight() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 5, 23, 30, 0).unwrap();
+
+        assert_eq!(
+            Timezone::new("+02:00").unwrap().naive(time),
+            date("2024-03-06 01:30:00").unwrap()
+        );
+        assert_eq!(
+            Timezone::new("-0530").unwrap().naive(time),
+            date("2024-03-05 18:00:00").unwrap()
+        );
+        assert_eq!(
+            Timezone::new("utc").unwrap().naive(time),
+            date("2024-03-05 23:30:00").unwrap()
+        );
+
+        let early = Utc.with_ymd_and_hms(2024, 1, 1, 0, 15, 0).unwrap();
+        assert_eq!(
+            Timezone::new("-01:00").unwrap().naive(early),
+            date("2023-12-31 23:15:00").unwrap()
+        );
+        assert_eq!(
+            Timezone::Local.naive(early),
+            early.with_timezone(&Local).naive_local()
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Reads the width and height of an image from its header, without decoding it.
+///
+/// Supports PNG, JPEG, GIF, WebP, BMP and TIFF.
+pub(crate) fn read(path: &Path) -> Option<(usize, usize)> {
+    from_reader(&mut BufReader::new(File::open(path).ok()?))
+}
+
+fn from_reader<R: Read + Seek>(file: &mut R) -> Option<(usize, usize)> {
+    let mut header = [0; 30];
+    let len = read_up_to(file, &mut header)?;
+    let header = &header[..len];
+
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png(header)
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        gif(header)
+    } else if header.starts_with(b"BM") {
+        bmp(header)
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        webp(header)
+    } else if header.starts_with(b"\xff\xd8") {
+        jpeg(file)
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        tiff(file, header.starts_with(b"II"))
+    } else {
+        None
+    }
+}
+
+fn png(header: &[u8]) -> Option<(usize, usize)> {
+    Some((be32(header, 16)? as usize, be32(header, 20)? as usize))
+}
+
+fn gif(header: &[u8]) -> Option<(usize, usize)> {
+    Some((le16(header, 6)? as usize, le16(header, 8)? as usize))
+}
+
+fn bmp(header: &[u8]) -> Option<(usize, usize)> {
+    // OS/2 bitmaps have a smaller header with 16-bit dimensions.
+    if le32(header, 14)? == 12 {
+        Some((le16(header, 18)? as usize, le16(header, 20)? as usize))
+    } else {
+        // The height is negative for images stored top-down.
+        let width = le32(header, 18)? as i32;
+        let height = le32(header, 22)? as i32;
+
+        Some((
+            width.unsigned_abs() as usize,
+            height.unsigned_abs() as usize,
+        ))
+    }
+}
+
+fn webp(header: &[u8]) -> Option<(usize, usize)> {
+    match header.get(12..16)? {
+        b"VP8 " => Some((
+            (le16(header, 26)? & 0x3fff) as usize,
+            (le16(header, 28)? & 0x3fff) as usize,
+        )),
+        b"VP8L" => {
+            let bits = le32(header, 21)?;
+
+            Some((
+                (bits & 0x3fff) as usize + 1,
+                ((bits >> 14) & 0x3fff) as usize + 1,
+            ))
+        }
+        b"VP8X" => Some((
+            le24(header, 24)? as usize + 1,
+            le24(header, 27)? as usize + 1,
+        )),
+        _ => None,
+    }
+}
+
+/// Walks the JPEG markers until the start of frame, which holds the dimensions.
+fn jpeg<R: Read + Seek>(file: &mut R) -> Option<(usize, usize)> {
+    file.seek(SeekFrom::Start(2)).ok()?;
+
+    loop {
+        let mut marker = [0; 2];
+        file.read_exact(&mut marker).ok()?;
+
+        if marker[0] != 0xff {
+            return None;
+        }
+
+        // Markers can be padded with any number of 0xff bytes.
+        let mut kind = marker[1];
+        while kind == 0xff {
+            let mut byte = [0; 1];
+            file.read_exact(&mut byte).ok()?;
+            kind = byte[0];
+        }
+
+        match kind {
+            // Markers without a segment.
+            0x01 | 0xd0..=0xd7 => continue,
+            // Start of scan or end of image, the frame header should have come before.
+            0xd9 | 0xda => return None,
+            _ => {}
+        }
+
+        let mut length = [0; 2];
+        file.read_exact(&mut length).ok()?;
+        let length = u16::from_be_bytes(length);
+
+        // The length counts its own two bytes.
+        if length < 2 {
+            return None;
+        }
+
+        // Start of frame markers, except DHT (c4), JPG (c8) and DAC (cc).
+        if matches!(kind, 0xc0..=0xcf) && !matches!(kind, 0xc4 | 0xc8 | 0xcc) {
+            let mut frame = [0; 5];
+            file.read_exact(&mut frame).ok()?;
+
+            return Some((be16(&frame, 3)? as usize, be16(&frame, 1)? as usize));
+        }
+
+        file.seek(SeekFrom::Current(i64::from(length) - 2)).ok()?;
+    }
+}
+
+/// Reads the dimensions from the first image file directory of a TIFF.
+fn tiff<R: Read + Seek>(file: &mut R, little_endian: bool) -> Option<(usize, usize)> {
+    let u16_at = |bytes: &[u8], offset| {
+        if little_endian {
+            le16(bytes, offset)
+        } else {
+            be16(bytes, offset)
+        }
+    };
+    let u32_at = |bytes: &[u8], offset| {
+        if little_endian {
+            le32(bytes, offset)
+        } else {
+            be32(bytes, offset)
+        }
+    };
+
+    let mut header = [0; 8];
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_exact(&mut header).ok()?;
+    file.seek(SeekFrom::Start(u32_at(&header, 4)?.into()))
+        .ok()?;
+
+    let mut count = [0; 2];
+    file.read_exact(&mut count).ok()?;
+
+    let mut width = None;
+    let mut height = None;
+
+    for _ in 0..u16_at(&count, 0)? {
+        let mut entry = [0; 12];
+        file.read_exact(&mut entry).ok()?;
+
+        // Dimensions are stored as either SHORT (3) or LONG (4) values.
+        let value = match u16_at(&entry, 2)? {
+            3 => u16_at(&entry, 8)?.into(),
+            4 => u32_at(&entry, 8)?,
+            _ => continue,
+        };
+
+        match u16_at(&entry, 0)? {
+            256 => width = Some(value as usize),
+            257 => height = Some(value as usize),
+            _ => {}
+        }
+
+        if let (Some(width), Some(height)) = (width, height) {
+            return Some((width, height));
+        }
+    }
+
+    None
+}
+
+/// Fills as much of the buffer as the file allows, returning how much was read.
+fn read_up_to<R: Read>(file: &mut R, buffer: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+
+    while len < buffer.len() {
+        match file.read(&mut buffer[len..]).ok()? {
+            0 => break,
+            n => len += n,
+        }
+    }
+
+    Some(len)
+}
+
+fn be16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le24(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 3)?;
+
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn le32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn dimensions(bytes: &[u8]) -> Option<(usize, usize)> {
+        from_reader(&mut Cursor::new(bytes))
+    }
+
+    /// Cutting the header anywhere before its end gives no dimensions, without panicking.
+    fn assert_truncated(bytes: &[u8], needed: usize) {
+        for len in 0..needed {
+            assert_eq!(dimensions(&bytes[..len]), None, "cut at {len}");
+        }
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend(640u32.to_be_bytes());
+        bytes.extend(480u32.to_be_bytes());
+        bytes.extend([8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn reads_png() {
+        assert_eq!(dimensions(&png()), Some((640, 480)));
+        assert_truncated(&png(), 24);
+    }
+
+    #[test]
+    fn reads_gif() {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend(300u16.to_le_bytes());
+        bytes.extend(200u16.to_le_bytes());
+
+        assert_eq!(dimensions(&bytes), Some((300, 200)));
+        assert_eq!(
+            dimensions(&[b"GIF87a".as_slice(), &bytes[6..]].concat()),
+            Some((300, 200))
+        );
+        assert_truncated(&bytes, 10);
+    }
+
+    #[test]
+    fn reads_bmp() {
+        let mut bytes = b"BM".to_vec();
+        bytes.extend([0; 12]);
+        bytes.extend(40u32.to_le_bytes());
+        bytes.extend(1024i32.to_le_bytes());
+        // Top-down bitmaps have a negative height.
+        bytes.extend((-768i32).to_le_bytes());
+
+        assert_eq!(dimensions(&bytes), Some((1024, 768)));
+        assert_truncated(&bytes, 26);
+
+        let mut os2 = b"BM".to_vec();
+        os2.extend([0; 12]);
+        os2.extend(12u32.to_le_bytes());
+        os2.extend(64u16.to_le_bytes());
+        os2.extend(32u16.to_le_bytes());
+
+        assert_eq!(dimensions(&os2), Some((64, 32)));
+    }
+
+    fn webp(chunk: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WEBP".to_vec();
+        bytes.extend(chunk);
+        bytes.extend([0; 4]);
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_webp() {
+        // Lossy: a frame tag and start code before 14-bit dimensions with a scale.
+        let mut lossy = vec![0; 3];
+        lossy.extend([0x9d, 0x01, 0x2a]);
+        lossy.extend((0x4000u16 | 800).to_le_bytes());
+        lossy.extend(600u16.to_le_bytes());
+        let lossy = webp(b"VP8 ", &lossy);
+
+        assert_eq!(dimensions(&lossy), Some((800, 600)));
+        assert_truncated(&lossy, 30);
+
+        // Lossless: a signature byte, then the width and height less one in 14 bits each.
+        let bits: u32 = (800 - 1) | ((600 - 1) << 14);
+        let mut lossless = vec![0x2f];
+        lossless.extend(bits.to_le_bytes());
+        let lossless = webp(b"VP8L", &lossless);
+
+        assert_eq!(dimensions(&lossless), Some((800, 600)));
+        assert_truncated(&lossless, 25);
+
+        // Extended: flags, then the width and height less one in 24 bits each.
+        let mut extended = vec![0; 4];
+        extended.extend(&(4000u32 - 1).to_le_bytes()[..3]);
+        extended.extend(&(3000u32 - 1).to_le_bytes()[..3]);
+        let extended = webp(b"VP8X", &extended);
+
+        assert_eq!(dimensions(&extended), Some((4000, 3000)));
+        assert_truncated(&extended, 30);
+        assert_eq!(dimensions(&webp(b"ALPH", &[0; 10])), None);
+    }
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xff, marker];
+        bytes.extend((data.len() as u16 + 2).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn frame(width: u16, height: u16) -> Vec<u8> {
+        let mut frame = vec![8];
+        frame.extend(height.to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.extend([3, 1, 0x22, 0]);
+        frame
+    }
+
+    #[test]
+    fn reads_jpeg() {
+        let bytes = [
+            b"\xff\xd8".to_vec(),
+            segment(0xe0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"),
+            segment(0xc4, &[0; 20]),
+            // Fill bytes before a marker.
+            vec![0xff, 0xff],
+            segment(0xc2, &frame(4032, 3024)),
+            b"\xff\xd9".to_vec(),
+        ]
+        .concat();
+
+        assert_eq!(dimensions(&bytes), Some((4032, 3024)));
+        assert_truncated(&bytes, bytes.len() - 6);
+    }
+
+    #[test]
+    fn rejects_jpeg_without_a_frame() {
+        let scan = [b"\xff\xd8".to_vec(), segment(0xda, &[0; 8])].concat();
+        let bad_length = [b"\xff\xd8".to_vec(), vec![0xff, 0xe1, 0, 1, 0xff, 0xc0]].concat();
+        let not_a_marker = b"\xff\xd8\x00\x10JFIF".to_vec();
+
+        assert_eq!(dimensions(&scan), None);
+        assert_eq!(dimensions(&bad_length), None);
+        assert_eq!(dimensions(&not_a_marker), None);
+    }
+
+    fn tiff(little_endian: bool) -> Vec<u8> {
+        let u16 = |v: u16| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let u32 = |v: u32| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let mut bytes = if little_endian {
+            b"II*\0".to_vec()
+        } else {
+            b"MM\0*".to_vec()
+        };
+
+        bytes.extend(u32(8));
+        bytes.extend(u16(3));
+        // An unrelated tag, the width as a SHORT and the height as a LONG.
+        for (tag, kind, value) in [(254, 4, 0), (256, 3, 1920), (257, 4, 1080)] {
+            bytes.extend(u16(tag));
+            bytes.extend(u16(kind));
+            bytes.extend(u32(1));
+            match kind {
+                3 => bytes.extend([u16(value as u16), [0, 0]].concat()),
+                _ => bytes.extend(u32(value)),
+            }
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn reads_tiff() {
+        for little_endian in [true, false] {
+            let bytes = tiff(little_endian);
+
+            assert_eq!(dimensions(&bytes), Some((1920, 1080)));
+            assert_truncated(&bytes, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(dimensions(b""), None);
+        assert_eq!(dimensions(b"%PDF-1.7"), None);
+        assert_eq!(dimensions(&[0; 64]), None);
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use file_format::{FileFormat, Kind};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fs::{self, Metadata},
+    path::Path,
+};
+
+/// What a directory contains, so it can be sorted as a whole. Only files are counted, through
+/// every subdirectory, and symlinks aren't followed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Summary {
+    /// The total size of the files in bytes.
+    pub(crate) size: u64,
+    pub(crate) count: usize,
+    /// The modification dates of the newest and oldest files.
+    pub(crate) newest: Option<DateTime<Utc>>,
+    pub(crate) oldest: Option<DateTime<Utc>>,
+    /// The kind most files are. It's `None` until it's read, since every file has to be opened.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "read"
+    )]
+    pub(crate) kind: Option<Option<Kind>>,
+}
+
+impl Summary {
+    /// Whether both summaries are of the same files, going by their sizes and dates. A cached
+    /// kind is only kept while they are.
+    pub(crate) fn same_files(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.count == other.count
+            && self.newest == other.newest
+            && self.oldest == other.oldest
+    }
+}
+
+fn read<'de, D>(deserializer: D) -> std::result::Result<Option<Option<Kind>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Walks a directory for the size, count and dates of its files, which only needs a stat each.
+pub(crate) fn summarize(path: &Path) -> Summary {
+    let mut summary = Summary::default();
+
+    walk(path, &mut |_, meta| {
+        summary.size += meta.len();
+        summary.count += 1;
+
+        if let Ok(modified) = meta.modified().map(DateTime::<Utc>::from) {
+            summary.newest = summary.newest.max(Some(modified));
+            summary.oldest = Some(summary.oldest.map_or(modified, |o| o.min(modified)));
+        }
+    });
+
+    summary
+}
+
+/// The kind most files in a directory are, from their content. Files that aren't recognized
+/// don't count, and ties go to the kind that comes first by name, so the result doesn't depend
+/// on the order files are listed in.
+pub(crate) fn dominant_kind(path: &Path) -> Option<Kind> {
+    let mut kinds: Vec<(Kind, usize)> = Vec::new();
+
+    walk(path, &mut |file, _| {
+        let format = match FileFormat::from_file(file) {
+            Ok(FileFormat::ArbitraryBinaryData) | Err(_) => return,
+            Ok(format) => format,
+        };
+
+        let kind = format.kind();
+
+        match kinds.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => *count += 1,
+            None => kinds.push((kind, 1)),
+        }
+    });
+
+    kinds
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| {
+            a_count
+                .cmp(b_count)
+                .then_with(|| format!("{b:?}").cmp(&format!("{a:?}")))
+        })
+        .map(|(kind, _)| kind)
+}
+
+/// Calls `f` with every file under the directory. Entries that can't be read are skipped.
+fn walk(path: &Path, f: &mut impl FnMut(&Path, &Metadata)) {
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            walk(&entry.path(), f);
+        } else if file_type.is_file() {
+            if let Ok(meta) = entry.metadata() {
+                f(&entry.path(), &meta);
+            }
+        }
+    }
+}
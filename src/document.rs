@@ -0,0 +1,364 @@
+use crate::xml;
+use chrono::{NaiveDate, NaiveDateTime};
+use file_format::{FileFormat, Kind};
+use lopdf::{Document, Object};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// The metadata of a document that can be used in a pattern.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct DocumentInfo {
+    pub(crate) title: Option<String>,
+    /// The first author, when there are several.
+    pub(crate) author: Option<String>,
+    /// The date the document was created, as written, without converting its time zone.
+    pub(crate) date: Option<NaiveDateTime>,
+    pub(crate) pages: Option<usize>,
+}
+
+impl DocumentInfo {
+    /// Reads the metadata of a PDF, EPUB, OOXML (DOCX, XLSX, PPTX) or OpenDocument file. Other
+    /// formats give `None`.
+    pub(crate) fn read(path: &Path, format: FileFormat) -> Option<Self> {
+        match format {
+            FileFormat::PortableDocumentFormat => pdf_info(path),
+            FileFormat::ElectronicPublication => epub_info(path),
+            FileFormat::OfficeOpenXmlDocument
+            | FileFormat::OfficeOpenXmlPresentation
+            | FileFormat::OfficeOpenXmlSpreadsheet => ooxml_info(path),
+            FileFormat::OpendocumentText
+            | FileFormat::OpendocumentPresentation
+            | FileFormat::OpendocumentSpreadsheet
+            | FileFormat::OpendocumentGraphics => odf_info(path),
+            _ => None,
+        }
+    }
+
+    /// Fills in what's missing from another source, e.g. XMP for a PDF.
+    fn or(self, other: Self) -> Self {
+        Self {
+            title: self.title.or(other.title),
+            author: self.author.or(other.author),
+            date: self.date.or(other.date),
+            pages: self.pages.or(other.pages),
+        }
+    }
+}
+
+/// Reads the Info dictionary of a PDF, without loading its content streams. XMP is only read
+/// when the title or author is missing, since that loads the whole document.
+fn pdf_info(path: &Path) -> Option<DocumentInfo> {
+    let metadata = Document::load_metadata(path).ok()?;
+    let info = DocumentInfo {
+        title: metadata.title.and_then(non_empty),
+        author: metadata.author.and_then(non_empty),
+        date: metadata.creation_date.as_deref().and_then(date),
+        pages: Some(metadata.page_count as usize),
+    };
+
+    if info.title.is_some() && info.author.is_some() {
+        return Some(info);
+    }
+
+    Some(match xmp(path) {
+        Some(xmp) => info.or(xmp_info(&xmp)),
+        None => info,
+    })
+}
+
+/// The XMP packet of a PDF, from the `Metadata` stream of its catalog.
+fn xmp(path: &Path) -> Option<String> {
+    let document = Document::load(path).ok()?;
+    let metadata = document.catalog().ok()?.get(b"Metadata").ok()?;
+    let Object::Stream(stream) = document.dereference(metadata).ok()?.1 else {
+        return None;
+    };
+    let content = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+fn xmp_info(xmp: &str) -> DocumentInfo {
+    let [title, author, date] = xml::first_texts(xmp, ["title", "creator", "CreateDate"]);
+
+    DocumentInfo {
+        title,
+        author,
+        date: date.as_deref().and_then(self::date),
+        pages: None,
+    }
+}
+
+/// Reads the package document of an EPUB, which `META-INF/container.xml` points to.
+fn epub_info(path: &Path) -> Option<DocumentInfo> {
+    let container = zip_entry(path, "META-INF/container.xml")?;
+    let package = zip_entry(path, &xml::attribute(&container, "rootfile", "full-path")?)?;
+
+    Some(opf_info(&package))
+}
+
+/// Reads the Dublin Core metadata of an EPUB package document.
+fn opf_info(package: &str) -> DocumentInfo {
+    let [title, author, date] = xml::first_texts(package, ["title", "creator", "date"]);
+
+    DocumentInfo {
+        title,
+        author,
+        date: date.as_deref().and_then(self::date),
+        pages: None,
+    }
+}
+
+/// Reads the core properties of an OOXML file, and its pages or slides from the extended ones.
+fn ooxml_info(path: &Path) -> Option<DocumentInfo> {
+    let core = zip_entry(path, "docProps/core.xml")?;
+    let [title, author, date] = xml::first_texts(&core, ["title", "creator", "created"]);
+    let pages = zip_entry(path, "docProps/app.xml").and_then(|app| {
+        let [pages, slides] = xml::first_texts(&app, ["Pages", "Slides"]);
+
+        pages.or(slides)?.parse().ok()
+    });
+
+    Some(DocumentInfo {
+        title,
+        author,
+        date: date.as_deref().and_then(self::date),
+        pages,
+    })
+}
+
+/// Reads the metadata of an OpenDocument file, preferring the author who created it to the
+/// one who last saved it.
+fn odf_info(path: &Path) -> Option<DocumentInfo> {
+    let meta = zip_entry(path, "meta.xml")?;
+    let [title, initial_creator, creator, date] = xml::first_texts(
+        &meta,
+        ["title", "initial-creator", "creator", "creation-date"],
+    );
+
+    Some(DocumentInfo {
+        title,
+        author: initial_creator.or(creator),
+        date: date.as_deref().and_then(self::date),
+        pages: xml::attribute(&meta, "document-statistic", "page-count")
+            .and_then(|pages| pages.parse().ok()),
+    })
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Parses an ISO 8601 date, as in XML metadata, or a PDF date like `D:20240131120000+01'00'`.
+/// Only the year is required.
+fn date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    let value = value.strip_prefix("D:").unwrap_or(value);
+
+    let fields: Vec<u32> = if value.get(4..5) == Some("-") {
+        // The fields of ISO dates are separated, and not always padded, e.g. `2024-1-5`. The
+        // time ends at a fraction of a second or the UTC offset.
+        let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, ""));
+        let time = time.split(['.', 'Z', '+', '-']).next().unwrap_or_default();
+
+        date.split('-')
+            .take(3)
+            .map(|field| field.trim_end_matches(|c: char| !c.is_ascii_digit()))
+            .chain(time.split(':').take(3).filter(|field| !field.is_empty()))
+            .map(|field| field.parse().ok())
+            .collect::<Option<_>>()?
+    } else {
+        // The fields of PDF dates have fixed widths, and the UTC offset follows them.
+        let digits: String = value.chars().take_while(char::is_ascii_digit).collect();
+
+        [0..4, 4..6, 6..8, 8..10, 10..12, 12..14]
+            .into_iter()
+            .map_while(|range| digits.get(range))
+            .map(|field| field.parse().ok())
+            .collect::<Option<_>>()?
+    };
+
+    let field = |index: usize, default: u32| fields.get(index).copied().unwrap_or(default);
+
+    NaiveDate::from_ymd_opt(
+        i32::try_from(*fields.first()?).ok()?,
+        field(1, 1),
+        field(2, 1),
+    )?
+    .and_hms_opt(field(3, 0), field(4, 0), field(5, 0))
+}
+
+/// How much of a document is read for its text.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limit {
+    /// The pages of a PDF read from the start.
+    pub(crate) pages: Option<usize>,
+    /// The bytes of text kept from the start.
+    pub(crate) bytes: Option<usize>,
+}
+
+/// Reads the text of a plain text file, PDF, DOCX or ODT. Other formats give `None`.
+pub(crate) fn text(path: &Path, format: FileFormat, limit: Limit) -> Option<String> {
+    let text = match format {
+        FileFormat::PortableDocumentFormat => pdf_text(path, limit.pages)?,
+        FileFormat::OfficeOpenXmlDocument => {
+            xml::text(&zip_entry(path, "word/document.xml")?, &["p", "br"])
+        }
+        FileFormat::OpendocumentText => {
+            xml::text(&zip_entry(path, "content.xml")?, &["p", "h", "line-break"])
+        }
+        // Only what's searched is read from text files, which can be large logs.
+        format if format.kind() == Kind::Text || format == FileFormat::PlainText => {
+            let file = File::open(path).ok()?;
+            let mut bytes = Vec::new();
+
+            match limit.bytes {
+                Some(limit) => file.take(limit as u64).read_to_end(&mut bytes).ok()?,
+                None => BufReader::new(file).read_to_end(&mut bytes).ok()?,
+            };
+
+            return Some(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        _ => return None,
+    };
+
+    Some(truncate(text, limit.bytes))
+}
+
+/// Extracts the text of the first pages of a PDF, or all of them.
+fn pdf_text(path: &Path, pages: Option<usize>) -> Option<String> {
+    let document = Document::load(path).ok()?;
+    let numbers: Vec<u32> = document
+        .get_pages()
+        .into_keys()
+        .take(pages.unwrap_or(usize::MAX))
+        .collect();
+
+    document.extract_text(&numbers).ok()
+}
+
+/// Reads a file from a ZIP based document.
+pub(crate) fn zip_entry(path: &Path, name: &str) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path).ok()?)).ok()?;
+    let mut content = String::new();
+
+    archive
+        .by_name(name)
+        .ok()?
+        .read_to_string(&mut content)
+        .ok()?;
+
+    Some(content)
+}
+
+/// Cuts text to at most `bytes`, at a character boundary.
+fn truncate(mut text: String, bytes: Option<usize>) -> String {
+    if let Some(mut end) = bytes.filter(|&bytes| bytes < text.len()) {
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        text.truncate(end);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(y: i32, m: u32, d: u32, h: u32, min: u32, sec: u32) -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(y, m, d)?.and_hms_opt(h, min, sec)
+    }
+
+    #[test]
+    fn pdf_dates() {
+        assert_eq!(
+            date("D:20240131120530+01'00'"),
+            datetime(2024, 1, 31, 12, 5, 30)
+        );
+        assert_eq!(date("D:20240131120530Z"), datetime(2024, 1, 31, 12, 5, 30));
+        assert_eq!(date("D:202401311205"), datetime(2024, 1, 31, 12, 5, 0));
+        assert_eq!(date("D:2024"), datetime(2024, 1, 1, 0, 0, 0));
+        assert_eq!(date("20240131"), datetime(2024, 1, 31, 0, 0, 0));
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(
+            date("2024-01-31T12:05:30Z"),
+            datetime(2024, 1, 31, 12, 5, 30)
+        );
+        assert_eq!(
+            date("2024-01-31T12:05:30+02:00"),
+            datetime(2024, 1, 31, 12, 5, 30)
+        );
+        assert_eq!(
+            date("2024-01-31T12:05:30-05:00"),
+            datetime(2024, 1, 31, 12, 5, 30)
+        );
+        assert_eq!(
+            date("2024-01-31T12:05:30.250Z"),
+            datetime(2024, 1, 31, 12, 5, 30)
+        );
+        assert_eq!(date("2024-01-31 12:05"), datetime(2024, 1, 31, 12, 5, 0));
+        assert_eq!(date("2024-01-31"), datetime(2024, 1, 31, 0, 0, 0));
+        assert_eq!(date("2024-1-5"), datetime(2024, 1, 5, 0, 0, 0));
+        assert_eq!(date("2024-01"), datetime(2024, 1, 1, 0, 0, 0));
+        assert_eq!(date("2024"), datetime(2024, 1, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(date(""), None);
+        assert_eq!(date("D:"), None);
+        assert_eq!(date("2024-13-01"), None);
+        assert_eq!(date("January 2024"), None);
+    }
+
+    #[test]
+    fn xmp_lists() {
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+      <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Annual Report</rdf:li></rdf:Alt></dc:title>
+      <dc:creator><rdf:Seq><rdf:li>Ada Lovelace</rdf:li><rdf:li>Charles Babbage</rdf:li></rdf:Seq></dc:creator>
+      <xmp:CreateDate>2024-03-05T09:00:00+01:00</xmp:CreateDate>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        let info = xmp_info(xmp);
+
+        assert_eq!(info.title.as_deref(), Some("Annual Report"));
+        assert_eq!(info.author.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(info.date, datetime(2024, 3, 5, 9, 0, 0));
+    }
+
+    #[test]
+    fn epub_package() {
+        let package = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:title>Moby-Dick</dc:title>
+    <dc:creator id="author">Herman Melville</dc:creator>
+    <dc:date>1851-10-18</dc:date>
+    <meta property="dcterms:modified">2024-01-01T00:00:00Z</meta>
+  </metadata>
+</package>"#;
+        let info = opf_info(package);
+
+        assert_eq!(info.title.as_deref(), Some("Moby-Dick"));
+        assert_eq!(info.author.as_deref(), Some("Herman Melville"));
+        assert_eq!(info.date, datetime(1851, 10, 18, 0, 0, 0));
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, TimeZone, Utc};
+use mailparse::{addrparse_header, dateparse, MailAddr, MailHeaderMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+/// The headers of a message are at its start, and are never this long.
+const HEADER_BYTES: u64 = 256 * 1024;
+
+/// The longest subject kept, in characters.
+const SUBJECT_LENGTH: usize = 80;
+
+/// The headers of an email that can be used in a pattern.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct EmailInfo {
+    /// The address of the sender, in lowercase.
+    pub(crate) from: Option<String>,
+    /// The address of the first recipient, in lowercase.
+    pub(crate) to: Option<String>,
+    /// The subject as a slug, e.g. `re-invoice-2023-04`.
+    pub(crate) subject: Option<String>,
+    pub(crate) sent: Option<DateTime<Utc>>,
+}
+
+impl EmailInfo {
+    /// Reads the RFC 5322 headers of an `.eml` file, decoding encoded words like
+    /// `=?UTF-8?B?...?=`.
+    pub(crate) fn read(path: &Path) -> Option<Self> {
+        let mut bytes = Vec::new();
+
+        File::open(path)
+            .ok()?
+            .take(HEADER_BYTES)
+            .read_to_end(&mut bytes)
+            .ok()?;
+
+        let (headers, _) = mailparse::parse_headers(&bytes).ok()?;
+        let address = |key: &str| {
+            let list = addrparse_header(headers.get_first_header(key)?).ok()?;
+
+            list.iter()
+                .find_map(|addr| match addr {
+                    MailAddr::Single(single) => Some(single.addr.to_lowercase()),
+                    MailAddr::Group(group) => group.addrs.first().map(|s| s.addr.to_lowercase()),
+                })
+                .filter(|addr| !addr.is_empty())
+        };
+
+        Some(Self {
+            from: address("From"),
+            to: address("To"),
+            subject: headers
+                .get_first_value("Subject")
+                .map(|subject| slug(&subject))
+                .filter(|subject| !subject.is_empty()),
+            sent: headers
+                .get_first_value("Date")
+                .and_then(|date| dateparse(&date).ok())
+                .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single()),
+        })
+    }
+
+    /// The domain of the sender's address.
+    pub(crate) fn sender_domain(&self) -> Option<&str> {
+        self.from
+            .as_deref()?
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .filter(|domain| !domain.is_empty())
+    }
+}
+
+/// Counts the messages of an mbox file, each of which starts with a `From ` line at the start
+/// of the file or after a blank line. Lines in the messages that start the same way are quoted
+/// as `>From `, though not by every program, which is why the blank line is needed.
+pub(crate) fn count_messages(path: &Path) -> Option<usize> {
+    count_in(BufReader::new(File::open(path).ok()?))
+}
+
+fn count_in(mut reader: impl BufRead) -> Option<usize> {
+    let mut line = Vec::new();
+    let mut count = 0;
+    let mut after_blank = true;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line).ok()? == 0 {
+            return Some(count);
+        }
+
+        if after_blank && line.starts_with(b"From ") {
+            count += 1;
+        }
+
+        after_blank = matches!(line.as_slice(), b"\n" | b"\r\n");
+    }
+}
+
+/// Turns text into lowercase words joined by dashes, so it can be used in a name anywhere,
+/// e.g. `Re: Invoice #2023/04` into `re-invoice-2023-04`.
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+
+        if !slug.is_empty() && slug.chars().count() + word.chars().count() >= SUBJECT_LENGTH {
+            break;
+        }
+
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+
+        slug.push_str(&word.to_lowercase());
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_start_after_a_blank_line() {
+        let mbox = b"From a@example.com Mon Jan  1 00:00:00 2024\nSubject: one\n\nHello,\nFrom here on, it's unquoted.\n>From here, it's quoted.\n\nFrom b@example.com Tue Jan  2 00:00:00 2024\r\nSubject: two\r\n\r\nFrom c@example.com Wed Jan  3 00:00:00 2024\n";
+
+        assert_eq!(count_in(&mbox[..]), Some(3));
+        assert_eq!(count_in(&b""[..]), Some(0));
+        assert_eq!(count_in(&b"Subject: not an mbox\n"[..]), Some(0));
+    }
+
+    #[test]
+    fn subjects_are_slugs() {
+        assert_eq!(slug("Re: Invoice #2023/04"), "re-invoice-2023-04");
+        assert_eq!(slug("  Ça va?  "), "ça-va");
+        assert_eq!(slug(&"word ".repeat(40)).len(), 79);
+    }
+}
@@ -36,8 +36,69 @@ pub enum AppError {
     UnkownToken,
     #[snafu(display("A specifier used in a pattern is unknown."))]
     UnkownSpecifier,
+    #[snafu(display("The specifier `{specifier}` can't be used with the `{token}` token."))]
+    InvalidSpecifier {
+        token: String,
+        specifier: String,
+    },
+    #[snafu(display("The threshold `{threshold}` can't be used with the `{token}` token."))]
+    InvalidThreshold {
+        token: String,
+        threshold: String,
+    },
+    #[snafu(display("The date regex `{format}` needs `year`, `month` and `day` groups."))]
+    InvalidDateRegex {
+        format: String,
+    },
+    #[snafu(display("`{fallback}` can't be used as a fallback date."))]
+    InvalidFallback {
+        fallback: String,
+    },
+    #[snafu(display(
+        "`{timezone}` isn't a timezone, use `local`, `utc` or an offset like `+02:00`."
+    ))]
+    InvalidTimezone {
+        timezone: String,
+    },
+    #[snafu(display("`contains` needs either `text` or `regex`."))]
+    InvalidSearch,
+    #[snafu(display("`{{match}}` needs a `contains` condition that isn't in `not` or `any`."))]
+    MissingSearch,
+    #[snafu(display("The kind `{kind}` is unknown."))]
+    InvalidKind {
+        kind: String,
+    },
+    #[snafu(display("`{name}` isn't a `user.*` extended attribute."))]
+    InvalidAttribute {
+        name: String,
+    },
+    #[snafu(display("`{tag}` isn't a tag, tags can't be empty or have a comma."))]
+    InvalidTag {
+        tag: String,
+    },
+    #[snafu(display("The media field `{field}` is unknown."))]
+    UnknownMediaField {
+        field: String,
+    },
+    #[snafu(display("The `media` token needs a field, e.g. `{{media:Video.BitRate}}`."))]
+    MissingMediaField,
+    #[snafu(display("The pattern `{pattern}` couldn't be parsed."))]
+    InvalidPattern {
+        pattern: String,
+    },
     #[snafu(display("A modifier used in a pattern is unknown."))]
     UnkownModifier,
+    #[snafu(display(
+        "The format `{format}` can't be used with the `{token}` token, only with `date`."
+    ))]
+    InvalidFormat {
+        token: String,
+        format: String,
+    },
+    #[snafu(display("The date format `{format}` used in a pattern is invalid."))]
+    InvalidDateFormat {
+        format: String,
+    },
     ItemError {
         message: String,
     },
@@ -1,19 +1,33 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, TimeZone, Utc};
 use file_format::{FileFormat, Kind};
+use mime_guess::Mime;
 use snafu::*;
 
 #[allow(unused_imports)]
 use crate::{
+    archive::{self, ArchiveInfo},
+    audio::AudioTags,
+    cache::{Extracted, FileId},
+    category::{self, Facts},
+    dimensions,
+    directory::{self, Summary},
+    document::{self, DocumentInfo},
+    email::{self, EmailInfo},
     error::AppError,
-    mediainfo::{self, MediaInfo, StreamKind},
+    mediainfo::{self, MediaInfo, MediaReport, StreamKind},
+    options::Context,
     parser::Specifier,
+    photo::PhotoInfo,
+    search::Search,
+    tags, users,
 };
 use std::{
+    collections::BTreeMap,
     ffi::OsString,
-    fs::{DirEntry, Metadata},
+    fs::{self, DirEntry, Metadata},
     os::unix::prelude::MetadataExt,
     path::PathBuf,
-    time::{SystemTime, UNIX_EPOCH},
+    time::SystemTime,
 };
 
 #[derive(Snafu, Debug)]
@@ -36,6 +50,12 @@ pub enum ItemError {
     Regex,
     #[snafu(display("Failed to parse int"))]
     ParseIntError,
+    #[snafu(display("Failed to parse float"))]
+    ParseFloatError,
+    #[snafu(display("Failed to open item with MediaInfo"))]
+    MediaInfo,
+    #[snafu(display("Failed to read the MediaInfo report of item"))]
+    MediaReport,
 }
 
 impl From<fs_extra::error::Error> for ItemError {
@@ -62,78 +82,166 @@ impl From<std::num::ParseIntError> for ItemError {
     }
 }
 
+impl From<std::num::ParseFloatError> for ItemError {
+    fn from(_value: std::num::ParseFloatError) -> Self {
+        Self::ParseFloatError
+    }
+}
+
 type Result<T> = std::result::Result<T, ItemError>;
 
-#[derive(Debug, Clone)]
+/// Landscape aspect ratios that images and videos are snapped to.
+const COMMON_ASPECT_RATIOS: [(usize, usize); 8] = [
+    (1, 1),
+    (5, 4),
+    (4, 3),
+    (3, 2),
+    (16, 10),
+    (16, 9),
+    (2, 1),
+    (21, 9),
+];
+
+/// How far a ratio can be from a common one and still be snapped to it.
+const ASPECT_RATIO_TOLERANCE: f64 = 0.02;
+
+/// Video resolution classes, as the minimum long side, minimum short side and name.
+const RESOLUTION_CLASSES: [(usize, usize, &str); 5] = [
+    (7680, 4320, "8K"),
+    (3840, 2160, "4K"),
+    (1920, 1080, "1080p"),
+    (1280, 720, "720p"),
+    (640, 480, "480p"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Orientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+impl Orientation {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Portrait => "portrait",
+            Self::Landscape => "landscape",
+            Self::Square => "square",
+        }
+    }
+}
+
+#[derive(Debug)]
 #[allow(dead_code)]
 pub struct Item<'i> {
     entry: &'i DirEntry,
-    meta: Metadata,
-    format: Option<FileFormat>,
-    media_info: Option<MediaInfo>,
+    /// Where the item is, which changes when an action renames it.
+    path: PathBuf,
+    context: &'i Context,
+    meta: Option<Metadata>,
+    media_info: Option<Option<MediaInfo>>,
+    /// Where the item is in the cache, when the Job reads metadata worth caching.
+    id: Option<FileId>,
+    extracted: Extracted,
+    /// Whether metadata was read that isn't in the cache yet.
+    extracted_changed: bool,
+    /// What a directory contains, walked once.
+    summary: Option<Option<Summary>>,
+    /// The groups of the last match of one of the Job's `contains` conditions, for `{match}`.
+    captures: Vec<Option<String>>,
 }
 
 #[allow(dead_code)]
 impl<'i> Item<'i> {
-    pub(crate) fn new(entry: &'i DirEntry) -> Result<Item<'i>> {
-        let format = if entry.path().is_dir() {
-            None
-        } else if let Ok(f) = FileFormat::from_file(entry.path()) {
-            Some(f)
-        } else {
-            None
+    /// Creates an item for an entry. Its metadata is read when it's first used, and only its
+    /// file system metadata is read here, when the Job reads content that may be cached.
+    pub(crate) fn new(entry: &'i DirEntry, context: &'i Context) -> Result<Item<'i>> {
+        let mut item = Item {
+            entry,
+            path: entry.path(),
+            context,
+            meta: None,
+            media_info: None,
+            id: None,
+            extracted: Extracted::default(),
+            extracted_changed: false,
+            summary: None,
+            captures: Vec::new(),
         };
 
-        if let Ok(meta) = entry.metadata() {
-            Ok(Item {
-                entry,
-                meta,
-                format,
-                media_info: None,
-            })
-        } else {
-            Err(ItemError::Metadata)
+        if let Some(cache) = &context.cache {
+            if context.needs.content && (item.is_file() || item.is_dir()) {
+                let id = FileId::new(item.meta()?);
+
+                if let Some(extracted) = cache.borrow().get(&id) {
+                    item.extracted = extracted.clone();
+                }
+
+                item.id = Some(id);
+            }
         }
+
+        Ok(item)
     }
 
-    pub(crate) fn format(&mut self) -> FileFormat {
-        if let Some(f) = &self.format {
-            f.to_owned()
-        } else {
-            let format =
-                FileFormat::from_file(self.entry.path()).expect("failed to get file format");
-            self.format = Some(format);
+    /// The file system metadata of the item, read once and kept for the other tokens.
+    fn meta(&mut self) -> Result<&Metadata> {
+        if self.meta.is_none() {
+            self.meta = fs::symlink_metadata(&self.path).ok();
+        }
+
+        self.meta.as_ref().ok_or(ItemError::Metadata)
+    }
+
+    /// The format of a file, sniffed from its content on first use.
+    pub(crate) fn format(&mut self) -> Option<FileFormat> {
+        if self.extracted.format.is_none() {
+            let format = if self.is_dir() {
+                None
+            } else {
+                FileFormat::from_file(self.path()).ok()
+            };
 
-            format
+            self.extracted.format = Some(format);
+            self.extracted_changed = true;
         }
+
+        self.extracted.format.flatten()
     }
 
-    pub(crate) fn media_info(&mut self) -> MediaInfo {
-        if let Some(m) = &self.media_info {
-            m.clone()
-        } else {
-            let mediainfo = MediaInfo::new();
-            self.media_info = Some(mediainfo.clone());
+    /// The MediaInfo handle of the item, opened on first use and shared by every media token.
+    /// It's closed when the item is dropped.
+    pub(crate) fn media_info(&mut self) -> Result<&MediaInfo> {
+        if self.media_info.is_none() {
+            let path = self.path();
+            let media_info = MediaInfo::new().and_then(|mut mi| mi.open(&path).then_some(mi));
 
-            mediainfo
+            self.media_info = Some(media_info);
         }
+
+        self.media_info
+            .as_ref()
+            .and_then(Option::as_ref)
+            .ok_or(ItemError::MediaInfo)
     }
 
+    // The type comes with the directory entry on most platforms, so it doesn't need a stat.
+
     pub(crate) fn is_file(&self) -> bool {
-        self.meta.is_file()
+        self.entry.file_type().is_ok_and(|t| t.is_file())
     }
 
     pub(crate) fn is_dir(&self) -> bool {
-        self.meta.is_dir()
+        self.entry.file_type().is_ok_and(|t| t.is_dir())
     }
 
     pub(crate) fn path(&self) -> PathBuf {
-        self.entry.path()
+        self.path.clone()
     }
 
     pub(crate) fn file_name(&self) -> Option<OsString> {
         if self.is_file() {
-            Some(self.entry.file_name())
+            self.path.file_name().map(OsString::from)
         } else {
             None
         }
@@ -142,10 +250,9 @@ impl<'i> Item<'i> {
     pub(crate) fn dir_name(&self) -> Option<String> {
         if self.is_dir() {
             Some(
-                self.entry
-                    .path()
+                self.path
                     .components()
-                    .last()
+                    .next_back()
                     .unwrap()
                     .as_os_str()
                     .to_str()?
@@ -156,150 +263,977 @@ impl<'i> Item<'i> {
         }
     }
 
-    pub(crate) fn created(&self) -> Result<NaiveDateTime> {
-        if let Ok(meta) = &self.meta.created() {
-            systemtime_to_date(meta)
-        } else {
-            Err(ItemError::Metadata)
+    /// When the item was created, from its birth time which std reads with `statx` on Linux.
+    /// File systems that don't record it fall back to the configured date.
+    pub(crate) fn created(&mut self) -> Result<NaiveDateTime> {
+        match self.meta()?.created() {
+            Ok(time) => Ok(self.local_date(time)),
+            Err(_) => match self.context.created_fallback {
+                Some(Specifier::Modified) => self.modified(),
+                Some(Specifier::Changed) => self.changed(),
+                Some(Specifier::Accessed) => self.accessed(),
+                _ => Err(ItemError::Metadata),
+            },
         }
     }
 
-    pub(crate) fn modified(&self) -> Result<NaiveDateTime> {
-        if let Ok(meta) = &self.meta.created() {
-            systemtime_to_date(meta)
-        } else {
-            Err(ItemError::Metadata)
-        }
+    pub(crate) fn modified(&mut self) -> Result<NaiveDateTime> {
+        let time = self.meta()?.modified()?;
+
+        Ok(self.local_date(time))
     }
 
-    pub(crate) fn accessed(&self) -> Result<NaiveDateTime> {
-        if let Ok(meta) = &self.meta.created() {
-            systemtime_to_date(meta)
-        } else {
-            Err(ItemError::Metadata)
-        }
+    /// When the item's metadata, e.g. its name or permissions, was last changed.
+    pub(crate) fn changed(&mut self) -> Result<NaiveDateTime> {
+        let meta = self.meta()?;
+        let time = Utc
+            .timestamp_opt(meta.ctime(), meta.ctime_nsec() as u32)
+            .single()
+            .ok_or(ItemError::ConvertTime)?;
+
+        Ok(self.context.timezone.naive(time))
     }
 
-    pub(crate) fn size(&self) -> u64 {
-        self.meta.size()
+    pub(crate) fn accessed(&mut self) -> Result<NaiveDateTime> {
+        let time = self.meta()?.accessed()?;
+
+        Ok(self.local_date(time))
     }
 
-    pub(crate) fn datetime(&self, specifier: Specifier) -> Result<NaiveDateTime> {
+    /// A filesystem time in the Job's timezone.
+    fn local_date(&self, time: SystemTime) -> NaiveDateTime {
+        self.context.timezone.naive(time.into())
+    }
+
+    pub(crate) fn size(&mut self) -> Result<u64> {
+        Ok(self.meta()?.size())
+    }
+
+    /// The uid of the item's owner.
+    pub(crate) fn owner(&mut self) -> Result<u32> {
+        Ok(self.meta()?.uid())
+    }
+
+    pub(crate) fn group(&mut self) -> Result<u32> {
+        Ok(self.meta()?.gid())
+    }
+
+    /// The name of the item's owner, or its uid when the system doesn't know it.
+    pub(crate) fn owner_name(&mut self) -> Result<String> {
+        let uid = self.owner()?;
+
+        Ok(users::user_name(uid).unwrap_or_else(|| uid.to_string()))
+    }
+
+    pub(crate) fn group_name(&mut self) -> Result<String> {
+        let gid = self.group()?;
+
+        Ok(users::group_name(gid).unwrap_or_else(|| gid.to_string()))
+    }
+
+    /// The permission bits of the item, including setuid, setgid and sticky.
+    pub(crate) fn mode(&mut self) -> Result<u32> {
+        Ok(self.meta()?.mode() & 0o7777)
+    }
+
+    /// Whether the item is a file anyone of the owner, group or others can execute.
+    pub(crate) fn is_executable(&mut self) -> Result<bool> {
+        Ok(self.is_file() && self.mode()? & 0o111 != 0)
+    }
+
+    pub(crate) fn is_world_writable(&mut self) -> Result<bool> {
+        Ok(self.mode()? & 0o002 != 0)
+    }
+
+    pub(crate) fn datetime(&mut self, specifier: Specifier) -> Result<NaiveDateTime> {
         match specifier {
             Specifier::Created => self.created(),
             Specifier::Modified => self.modified(),
+            Specifier::Changed => self.changed(),
             Specifier::Accessed => self.accessed(),
+            Specifier::Filename => self.filename_date().or_else(|_| self.fallback_date()),
+            Specifier::Taken => self.taken().or_else(|_| self.fallback_date()),
+            Specifier::Tag => self.tagged_date().or_else(|_| self.fallback_date()),
+            Specifier::Docdate => self.document_date().or_else(|_| self.fallback_date()),
+            Specifier::Sent => self.sent().or_else(|_| self.fallback_date()),
+            Specifier::Newest => self.newest(),
+            Specifier::Oldest => self.oldest(),
             _ => Err(ItemError::UnknownSpecifier),
         }
     }
 
-    pub(crate) fn kind(&self) -> Option<Kind> {
-        self.format.as_ref().map(|f| f.kind())
+    /// Reads a date from the name of the item.
+    pub(crate) fn filename_date(&self) -> Result<NaiveDateTime> {
+        let name = self.path.file_name().unwrap_or_default();
+
+        self.context
+            .filename_dates
+            .parse(&name.to_string_lossy())
+            .ok_or(ItemError::Failure)
+    }
+
+    /// The date a photo was taken, from its EXIF metadata.
+    pub(crate) fn taken(&mut self) -> Result<NaiveDateTime> {
+        self.photo()
+            .and_then(|photo| photo.taken)
+            .ok_or(ItemError::Failure)
+    }
+
+    /// The EXIF metadata of an image, read once and kept for the other tokens.
+    pub(crate) fn photo(&mut self) -> Option<&PhotoInfo> {
+        if self.extracted.photo.is_none() {
+            let photo = match self.kind() {
+                Some(Kind::Image) => PhotoInfo::read(&self.path()),
+                _ => None,
+            };
+
+            self.extracted.photo = Some(photo);
+            self.extracted_changed = true;
+        }
+
+        self.extracted.photo.as_ref().and_then(Option::as_ref)
+    }
+
+    /// The recording date from the tags of an audio file.
+    pub(crate) fn tagged_date(&mut self) -> Result<NaiveDateTime> {
+        self.audio_tags()
+            .and_then(|tags| tags.date)
+            .ok_or(ItemError::Failure)
+    }
+
+    /// The tags of an audio file, read once and kept for the other tokens.
+    pub(crate) fn audio_tags(&mut self) -> Option<&AudioTags> {
+        if self.extracted.audio.is_none() {
+            let tags = match self.kind() {
+                Some(Kind::Audio) => AudioTags::read(&self.path()),
+                _ => None,
+            };
+
+            self.extracted.audio = Some(tags);
+            self.extracted_changed = true;
+        }
+
+        self.extracted.audio.as_ref().and_then(Option::as_ref)
+    }
+
+    /// The filesystem date the Job falls back to when a date can't be read from the item.
+    fn fallback_date(&mut self) -> Result<NaiveDateTime> {
+        match &self.context.date_fallback {
+            Some(Specifier::Created) => self.created(),
+            Some(Specifier::Modified) => self.modified(),
+            Some(Specifier::Changed) => self.changed(),
+            Some(Specifier::Accessed) => self.accessed(),
+            _ => Err(ItemError::Failure),
+        }
+    }
+
+    pub(crate) fn kind(&mut self) -> Option<Kind> {
+        self.format().map(|f| f.kind())
+    }
+
+    /// What a directory contains. It's walked on first use, and only the dominant kind comes
+    /// from the cache, since the sizes and dates are needed to tell whether it's still right.
+    pub(crate) fn summary(&mut self) -> Option<&Summary> {
+        if self.summary.is_none() {
+            let summary = self.is_dir().then(|| {
+                let mut summary = directory::summarize(&self.path());
+
+                if let Some(Some(cached)) = &self.extracted.directory {
+                    if cached.same_files(&summary) {
+                        summary.kind = cached.kind;
+                    }
+                }
+
+                summary
+            });
+
+            self.summary = Some(summary);
+        }
+
+        self.summary.as_ref().and_then(Option::as_ref)
+    }
+
+    /// The kind most files in a directory are.
+    pub(crate) fn dominant_kind(&mut self) -> Option<Kind> {
+        let path = self.path();
+
+        self.summary()?;
+        let summary = self.summary.as_mut().and_then(Option::as_mut)?;
+
+        if summary.kind.is_none() {
+            summary.kind = Some(directory::dominant_kind(&path));
+
+            self.extracted.directory = Some(Some(summary.clone()));
+            self.extracted_changed = true;
+        }
+
+        summary.kind.flatten()
+    }
+
+    /// The size of a file, or the total size of the files in a directory.
+    pub(crate) fn total_size(&mut self) -> Result<u64> {
+        if self.is_dir() {
+            self.summary().map(|s| s.size).ok_or(ItemError::Failure)
+        } else {
+            self.size()
+        }
+    }
+
+    /// The number of files in a directory and its subdirectories, or of messages in an mbox
+    /// file.
+    pub(crate) fn count(&mut self) -> Result<usize> {
+        if self.is_file() {
+            return self.messages();
+        }
+
+        self.summary().map(|s| s.count).ok_or(ItemError::Failure)
+    }
+
+    /// The date of the most recently modified file in a directory, or of a file itself. Empty
+    /// directories use their own date.
+    pub(crate) fn newest(&mut self) -> Result<NaiveDateTime> {
+        match self.summary().and_then(|s| s.newest) {
+            Some(time) => Ok(self.context.timezone.naive(time)),
+            None => self.modified(),
+        }
+    }
+
+    pub(crate) fn oldest(&mut self) -> Result<NaiveDateTime> {
+        match self.summary().and_then(|s| s.oldest) {
+            Some(time) => Ok(self.context.timezone.naive(time)),
+            None => self.modified(),
+        }
+    }
+
+    /// The first of the Job's categories the item is in. Directories are put in a category by
+    /// the kind most of their files are.
+    pub(crate) fn category(&mut self) -> Option<&'i str> {
+        let categories = &self.context.categories;
+
+        if categories.is_empty() {
+            return None;
+        }
+
+        if self.is_dir() {
+            let kind = self.dominant_kind();
+
+            return categories.find(&Facts {
+                kind,
+                ..Facts::default()
+            });
+        }
+
+        let format = self.format();
+        let mime = self.mime();
+        let extension = self
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+
+        categories.find(&Facts {
+            kind: format.map(|format| format.kind()),
+            extension: extension.as_deref(),
+            mime: mime.as_ref().map(|mime| mime.essence_str()),
+            format,
+        })
+    }
+
+    /// The extension the content of a file calls for, e.g. `exe` for a Windows executable.
+    /// Formats many kinds of files share, like plain text or ZIP, don't have one.
+    pub(crate) fn true_extension(&mut self) -> Option<String> {
+        if !self.is_file() {
+            return None;
+        }
+
+        self.format()
+            .filter(|format| !is_generic(*format))
+            .map(|format| format.extension().to_string())
+    }
+
+    /// Whether the content of a file isn't what its extension says, e.g. a `.jpg` that's an
+    /// executable. Files without an extension, or in a format many kinds of files share, are
+    /// never mismatched.
+    pub(crate) fn is_mismatched(&mut self) -> bool {
+        let Some(extension) = self.path.extension() else {
+            return false;
+        };
+
+        let extension = extension.to_string_lossy().to_lowercase();
+
+        match self.format() {
+            Some(format) if self.is_file() => is_mismatched(format, &extension),
+            _ => false,
+        }
+    }
+
+    /// Detects the mime type from the content of a file, falling back to its name when the
+    /// content isn't recognized.
+    pub(crate) fn mime(&mut self) -> Option<Mime> {
+        if !self.is_file() {
+            return None;
+        }
+
+        let format = self.format();
+        let sniffed = format.and_then(|format| match format {
+            FileFormat::ArbitraryBinaryData | FileFormat::PlainText => None,
+            format => format.media_type().parse::<Mime>().ok(),
+        });
+
+        sniffed
+            .or_else(|| mime_guess::from_path(self.path()).first())
+            .or_else(|| format.and_then(|format| format.media_type().parse::<Mime>().ok()))
     }
 
     pub(crate) fn move_to(&self, dest: PathBuf) -> Result<()> {
         if self.is_dir() {
-            fs_extra::dir::move_dir(self.entry.path(), dest, &fs_extra::dir::CopyOptions::new())?;
+            fs_extra::dir::move_dir(&self.path, dest, &fs_extra::dir::CopyOptions::new())?;
         } else {
-            fs_extra::file::move_file(
-                self.entry.path(),
-                dest,
-                &fs_extra::file::CopyOptions::new(),
-            )?;
+            fs_extra::file::move_file(&self.path, dest, &fs_extra::file::CopyOptions::new())?;
         }
 
         Ok(())
     }
 
+    /// Renames the item in its directory.
+    pub(crate) fn rename_to(&mut self, dest: PathBuf) -> Result<()> {
+        fs::rename(&self.path, &dest)?;
+        self.path = dest;
+
+        Ok(())
+    }
+
     pub(crate) fn _copy_to(&self, dest: PathBuf) -> Result<()> {
         if self.is_dir() {
-            fs_extra::dir::copy(self.entry.path(), dest, &fs_extra::dir::CopyOptions::new())?;
+            fs_extra::dir::copy(&self.path, dest, &fs_extra::dir::CopyOptions::new())?;
         } else {
-            fs_extra::file::copy(self.entry.path(), dest, &fs_extra::file::CopyOptions::new())?;
+            fs_extra::file::copy(&self.path, dest, &fs_extra::file::CopyOptions::new())?;
         }
 
         Ok(())
     }
 
-    pub(crate) fn width(&self) -> Result<usize> {
-        if let Some(format) = &self.format {
-            match format.kind() {
-                Kind::Image => {
-                    let mi = MediaInfo::new();
-                    if mi.open(self.entry.path().to_str().unwrap()) {
-                        let width: usize = mi.get_string(StreamKind::Image, "Width").parse()?;
+    pub(crate) fn width(&mut self) -> Result<usize> {
+        Ok(self.dimensions()?.0)
+    }
 
-                        mi.close();
+    pub(crate) fn height(&mut self) -> Result<usize> {
+        Ok(self.dimensions()?.1)
+    }
 
-                        Ok(width)
-                    } else {
-                        Err(ItemError::Open)
-                    }
-                }
-                Kind::Video => {
-                    let mi = MediaInfo::new();
-                    if mi.open(self.entry.path().to_str().unwrap()) {
-                        let width: usize = mi.get_string(StreamKind::Video, "Width").parse()?;
+    /// Width and height of an image or video as it's shown, so rotated photos and videos are
+    /// swapped. Images are measured from their header, and with MediaInfo when it's a format the
+    /// header parser doesn't know. Videos need MediaInfo.
+    fn dimensions(&mut self) -> Result<(usize, usize)> {
+        if self.extracted.dimensions.is_none() {
+            let dimensions = match self.visual_stream()? {
+                StreamKind::Image => dimensions::read(&self.path())
+                    .or_else(|| self.media_dimensions(StreamKind::Image).ok()),
+                stream_kind => self.media_dimensions(stream_kind).ok(),
+            };
 
-                        mi.close();
+            // Without MediaInfo nothing is cached, so the dimensions it would read are read once
+            // it's installed.
+            if dimensions.is_none() && !mediainfo::available() {
+                return Err(ItemError::MediaInfo);
+            }
 
-                        Ok(width)
-                    } else {
-                        Err(ItemError::Open)
-                    }
-                }
-                _ => Err(ItemError::Failure),
+            let rotated = dimensions.is_some() && self.is_rotated();
+
+            self.extracted.dimensions =
+                Some(dimensions.map(|(w, h)| if rotated { (h, w) } else { (w, h) }));
+            self.extracted_changed = true;
+        }
+
+        self.extracted
+            .dimensions
+            .flatten()
+            .ok_or(ItemError::Failure)
+    }
+
+    /// Whether the item is shown turned a quarter, from the EXIF orientation of a photo or the
+    /// rotation of a video.
+    fn is_rotated(&mut self) -> bool {
+        match self.kind() {
+            Some(Kind::Image) => self.photo().is_some_and(|photo| photo.rotated),
+            Some(Kind::Video) => self
+                .media_string(StreamKind::Video, "Rotation")
+                .ok()
+                .and_then(|rotation| rotation.parse::<f64>().ok())
+                .is_some_and(|rotation| (rotation.round() as i64).rem_euclid(180) == 90),
+            _ => false,
+        }
+    }
+
+    fn media_dimensions(&mut self, stream_kind: StreamKind) -> Result<(usize, usize)> {
+        Ok((
+            self.media_string(stream_kind, "Width")?.parse()?,
+            self.media_string(stream_kind, "Height")?.parse()?,
+        ))
+    }
+
+    pub(crate) fn orientation(&mut self) -> Result<Orientation> {
+        let (width, height) = (self.width()?, self.height()?);
+
+        Ok(match width.cmp(&height) {
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Equal => Orientation::Square,
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+        })
+    }
+
+    /// Aspect ratio as `WxH`, snapped to the nearest common ratio when it's close to one.
+    pub(crate) fn aspect_ratio(&mut self) -> Result<String> {
+        let (width, height) = (self.width()?, self.height()?);
+
+        if width == 0 || height == 0 {
+            return Err(ItemError::Failure);
+        }
+
+        let ratio = width as f64 / height as f64;
+        let portrait = height > width;
+
+        for (w, h) in COMMON_ASPECT_RATIOS {
+            let (w, h) = if portrait { (h, w) } else { (w, h) };
+
+            if (ratio / (w as f64 / h as f64) - 1.).abs() <= ASPECT_RATIO_TOLERANCE {
+                return Ok(format!("{w}x{h}"));
             }
+        }
+
+        let divisor = gcd(width, height);
+
+        Ok(format!("{}x{}", width / divisor, height / divisor))
+    }
+
+    pub(crate) fn megapixels(&mut self) -> Result<f64> {
+        Ok((self.width()? * self.height()?) as f64 / 1_000_000.)
+    }
+
+    /// Named resolution class of a video, judged by its long and short sides so portrait and
+    /// letterboxed videos land in the same class as their landscape versions.
+    pub(crate) fn resolution(&mut self) -> Result<&'static str> {
+        if !matches!(self.kind(), Some(Kind::Video)) {
+            return Err(ItemError::Failure);
+        }
+
+        let (width, height) = (self.width()?, self.height()?);
+        let (long, short) = (width.max(height), width.min(height));
+
+        Ok(RESOLUTION_CLASSES
+            .iter()
+            .find(|(l, s, _)| long >= *l || short >= *s)
+            .map_or("SD", |(_, _, name)| name))
+    }
+
+    /// Duration of audio and video files in seconds.
+    pub(crate) fn duration(&mut self) -> Result<f64> {
+        self.playable()?;
+        let millis: f64 = self
+            .media_string(StreamKind::General, "Duration")?
+            .parse()?;
+
+        Ok(millis / 1000.)
+    }
+
+    /// Overall bitrate of audio and video files in bits per second.
+    pub(crate) fn bitrate(&mut self) -> Result<f64> {
+        self.playable()?;
+
+        Ok(self
+            .media_string(StreamKind::General, "OverallBitRate")?
+            .parse()?)
+    }
+
+    /// Container format of audio and video files, e.g. `MPEG-4` or `Matroska`.
+    pub(crate) fn container(&mut self) -> Result<String> {
+        self.playable()?;
+        self.media_value(StreamKind::General, "Format")
+    }
+
+    /// Format of the first video stream, e.g. `AVC` or `HEVC`.
+    pub(crate) fn video_codec(&mut self) -> Result<String> {
+        self.video()?;
+        self.media_value(StreamKind::Video, "Format")
+    }
+
+    /// Frames per second of the first video stream.
+    pub(crate) fn frame_rate(&mut self) -> Result<f64> {
+        self.video()?;
+
+        Ok(self.media_value(StreamKind::Video, "FrameRate")?.parse()?)
+    }
+
+    /// Bits per sample of the first video stream.
+    pub(crate) fn bit_depth(&mut self) -> Result<usize> {
+        self.video()?;
+
+        Ok(self.media_value(StreamKind::Video, "BitDepth")?.parse()?)
+    }
+
+    /// HDR format of the first video stream, e.g. `Dolby Vision` or `SMPTE ST 2086`, or
+    /// `SDR` when the video has none.
+    pub(crate) fn hdr_format(&mut self) -> Result<String> {
+        self.video()?;
+        let hdr = self.media_string(StreamKind::Video, "HDR_Format")?;
+
+        if hdr.is_empty() {
+            Ok("SDR".to_string())
         } else {
-            Err(ItemError::Format)
+            Ok(hdr)
         }
     }
 
-    pub(crate) fn height(&self) -> Result<usize> {
-        if let Some(format) = &self.format {
-            match format.kind() {
-                Kind::Image => {
-                    let mi = MediaInfo::new();
-                    if mi.open(self.entry.path().to_str().unwrap()) {
-                        let height: usize = mi.get_string(StreamKind::Image, "Height").parse()?;
+    /// Languages of the audio streams of a video.
+    pub(crate) fn audio_languages(&mut self) -> Result<Vec<String>> {
+        self.video()?;
+        self.stream_languages(StreamKind::Audio)
+    }
 
-                        mi.close();
+    /// Languages of the subtitle streams of a video.
+    pub(crate) fn subtitle_languages(&mut self) -> Result<Vec<String>> {
+        self.video()?;
+        self.stream_languages(StreamKind::Text)
+    }
 
-                        Ok(height)
-                    } else {
-                        Err(ItemError::Open)
-                    }
-                }
-                Kind::Video => {
-                    let mi = MediaInfo::new();
-                    if mi.open(self.entry.path().to_str().unwrap()) {
-                        let height: usize = mi.get_string(StreamKind::Video, "Height").parse()?;
+    /// Number of audio streams of a video.
+    pub(crate) fn audio_tracks(&mut self) -> Result<usize> {
+        self.video()?;
+        self.media_count(StreamKind::Audio)
+    }
 
-                        mi.close();
+    /// Number of subtitle streams of a video.
+    pub(crate) fn subtitle_tracks(&mut self) -> Result<usize> {
+        self.video()?;
+        self.media_count(StreamKind::Text)
+    }
 
-                        Ok(height)
-                    } else {
-                        Err(ItemError::Open)
-                    }
-                }
-                _ => Err(ItemError::Failure),
+    /// Whether any of the subtitle streams of a video is forced.
+    pub(crate) fn has_forced_subtitle(&mut self) -> Result<bool> {
+        self.video()?;
+        Ok(self
+            .media_strings(StreamKind::Text, "Forced")?
+            .iter()
+            .any(|forced| forced == "Yes"))
+    }
+
+    /// The distinct languages of the streams of a kind, skipping streams without one.
+    fn stream_languages(&mut self, stream_kind: StreamKind) -> Result<Vec<String>> {
+        let languages = self.media_strings(stream_kind, "Language/String")?;
+        let mut distinct: Vec<String> = Vec::new();
+
+        for language in languages.into_iter().filter(|l| !l.is_empty()) {
+            if !distinct.contains(&language) {
+                distinct.push(language);
             }
+        }
+
+        if distinct.is_empty() {
+            Err(ItemError::Failure)
         } else {
-            Err(ItemError::Format)
+            Ok(distinct)
         }
     }
-}
 
-/// Convert SystemTime into the DateTime it represents.
-fn systemtime_to_date(time: &SystemTime) -> Result<NaiveDateTime> {
-    if let Ok(time_since) = time.duration_since(UNIX_EPOCH) {
-        if let Some(datetime) = NaiveDateTime::from_timestamp_millis(time_since.as_millis() as i64)
-        {
-            Ok(datetime)
+    /// The tags of the item, from `user.xdg.tags`. They're read each time since actions can
+    /// change them.
+    pub(crate) fn tags(&self) -> Vec<String> {
+        tags::read(&self.path())
+    }
+
+    pub(crate) fn add_tag(&self, tag: &str) -> Result<()> {
+        let mut tags = self.tags();
+
+        if tags::add(&mut tags, tag) {
+            tags::write(&self.path(), &tags)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn remove_tag(&self, tag: &str) -> Result<()> {
+        let mut tags = self.tags();
+
+        if tags::remove(&mut tags, tag) {
+            tags::write(&self.path(), &tags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the item has a `user.*` extended attribute.
+    pub(crate) fn has_attribute(&self, name: &str) -> bool {
+        tags::has_attribute(&self.path(), name)
+    }
+
+    /// The metadata of a PDF, EPUB, OOXML or OpenDocument file, read once and kept for the
+    /// other tokens.
+    pub(crate) fn document_info(&mut self) -> Option<&DocumentInfo> {
+        if self.extracted.document.is_none() {
+            let info = match self.format() {
+                Some(format) if self.is_file() => DocumentInfo::read(&self.path, format),
+                _ => None,
+            };
+
+            self.extracted.document = Some(info);
+            self.extracted_changed = true;
+        }
+
+        self.extracted.document.as_ref().and_then(Option::as_ref)
+    }
+
+    /// Number of pages in a PDF, DOCX or OpenDocument text, or slides in a presentation.
+    pub(crate) fn pages(&mut self) -> Result<usize> {
+        self.document_info()
+            .and_then(|info| info.pages)
+            .ok_or(ItemError::Failure)
+    }
+
+    /// The date a document was created, from its metadata.
+    pub(crate) fn document_date(&mut self) -> Result<NaiveDateTime> {
+        self.document_info()
+            .and_then(|info| info.date)
+            .ok_or(ItemError::Failure)
+    }
+
+    /// Whether the item is a file with the extension, in any case.
+    fn has_extension(&self, extension: &str) -> bool {
+        self.is_file()
+            && self
+                .path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+    }
+
+    /// The headers of an `.eml` file, read once and kept for the other tokens. Emails are
+    /// text, so they're told apart by their extension, which is checked before the cache as a
+    /// rename doesn't change where the file is in it.
+    pub(crate) fn email(&mut self) -> Option<&EmailInfo> {
+        if !self.has_extension("eml") {
+            return None;
+        }
+
+        if self.extracted.email.is_none() {
+            self.extracted.email = Some(EmailInfo::read(&self.path));
+            self.extracted_changed = true;
+        }
+
+        self.extracted.email.as_ref().and_then(Option::as_ref)
+    }
+
+    /// When an email was sent, from its `Date` header.
+    pub(crate) fn sent(&mut self) -> Result<NaiveDateTime> {
+        let sent = self
+            .email()
+            .and_then(|email| email.sent)
+            .ok_or(ItemError::Failure)?;
+
+        Ok(self.context.timezone.naive(sent))
+    }
+
+    /// The number of messages in an `.mbox` file, which is told apart by its extension like
+    /// emails are.
+    pub(crate) fn messages(&mut self) -> Result<usize> {
+        if !self.has_extension("mbox") {
+            return Err(ItemError::Failure);
+        }
+
+        if self.extracted.messages.is_none() {
+            self.extracted.messages = Some(email::count_messages(&self.path));
+            self.extracted_changed = true;
+        }
+
+        self.extracted.messages.flatten().ok_or(ItemError::Failure)
+    }
+
+    /// What's in a ZIP, tar or 7z archive, listed without extracting it.
+    pub(crate) fn archive(&mut self) -> Option<&ArchiveInfo> {
+        if self.extracted.archive.is_none() {
+            let info = match self.format() {
+                Some(format) if self.is_file() => archive::read(&self.path, format),
+                _ => None,
+            };
+
+            self.extracted.archive = Some(info);
+            self.extracted_changed = true;
+        }
+
+        self.extracted.archive.as_ref().and_then(Option::as_ref)
+    }
+
+    /// The number of files in an archive.
+    pub(crate) fn archive_count(&mut self) -> Result<usize> {
+        self.archive()
+            .map(|info| info.count)
+            .ok_or(ItemError::Failure)
+    }
+
+    /// The kind most files in an archive are, going by their names. The Job's categories come
+    /// first, as they do for `{kind}`, then the kind of the extension.
+    pub(crate) fn archive_kind(&mut self) -> Option<String> {
+        let categories = &self.context.categories;
+        let mut kinds: BTreeMap<String, usize> = BTreeMap::new();
+
+        for (extension, count) in &self.archive()?.extensions {
+            let kind = category::extension_kind(extension);
+            let mime = mime_guess::from_ext(extension).first();
+            let facts = Facts {
+                kind: Some(kind),
+                extension: Some(extension),
+                mime: mime.as_ref().map(|mime| mime.essence_str()),
+                ..Facts::default()
+            };
+
+            let name = match (categories.find(&facts), kind) {
+                (Some(category), _) => category.to_string(),
+                // Named by the mime type, as `{kind}` does for binary content.
+                (None, Kind::Application) => match &mime {
+                    Some(mime) => mime.type_().to_string(),
+                    None => continue,
+                },
+                (None, kind) => category::kind_name(kind).to_string(),
+            };
+
+            *kinds.entry(name).or_default() += count;
+        }
+
+        // Ties go to the kind that comes first by name.
+        kinds
+            .into_iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+            .map(|(kind, _)| kind)
+    }
+
+    /// Searches the text of a file, giving the groups of the match.
+    pub(crate) fn search(&mut self, search: &Search) -> Option<Vec<Option<String>>> {
+        let format = match self.format() {
+            Some(format) if self.is_file() => format,
+            _ => return None,
+        };
+
+        search.find(&document::text(&self.path, format, search.limit())?)
+    }
+
+    /// Keeps the groups of the match of a `contains` condition for `{match}`.
+    pub(crate) fn keep_captures(&mut self, captures: Vec<Option<String>>) {
+        self.captures = captures;
+    }
+
+    /// The text of a group of the last match, where the whole match is group 0.
+    pub(crate) fn capture(&self, group: usize) -> Option<&str> {
+        self.captures.get(group)?.as_deref()
+    }
+
+    /// The MediaInfo stream that holds the dimensions of images and videos.
+    fn visual_stream(&mut self) -> Result<StreamKind> {
+        match self.kind() {
+            Some(Kind::Image) => Ok(StreamKind::Image),
+            Some(Kind::Video) => Ok(StreamKind::Video),
+            Some(_) => Err(ItemError::Failure),
+            None => Err(ItemError::Format),
+        }
+    }
+
+    /// The full MediaInfo report of an image, audio or video file, read once and kept for
+    /// the other tokens.
+    pub(crate) fn media_report(&mut self) -> Result<&MediaReport> {
+        if self.extracted.media_report.is_none() {
+            let report = match self.kind() {
+                Some(Kind::Image | Kind::Audio | Kind::Video) => self.media_info()?.report(),
+                _ => None,
+            };
+
+            self.extracted.media_report = Some(report);
+            self.extracted_changed = true;
+        }
+
+        self.extracted
+            .media_report
+            .as_ref()
+            .and_then(Option::as_ref)
+            .ok_or(ItemError::MediaReport)
+    }
+
+    /// Checks that the item is a video file.
+    fn video(&mut self) -> Result<()> {
+        match self.kind() {
+            Some(Kind::Video) => Ok(()),
+            Some(_) => Err(ItemError::Failure),
+            None => Err(ItemError::Format),
+        }
+    }
+
+    /// Checks that the item is an audio or video file.
+    fn playable(&mut self) -> Result<()> {
+        match self.kind() {
+            Some(Kind::Audio | Kind::Video) => Ok(()),
+            Some(_) => Err(ItemError::Failure),
+            None => Err(ItemError::Format),
+        }
+    }
+
+    /// Reads a field from the first stream of a kind with MediaInfo.
+    fn media_string(&mut self, stream_kind: StreamKind, field: &str) -> Result<String> {
+        self.media_string_at(stream_kind, 0, field)
+    }
+
+    /// Reads a field from the stream of a kind at an index with MediaInfo.
+    fn media_string_at(
+        &mut self,
+        stream_kind: StreamKind,
+        index: usize,
+        field: &str,
+    ) -> Result<String> {
+        let key = format!("{}/{index}/{field}", stream_kind.name());
+
+        self.with_media_info(key, |mi| mi.get(stream_kind, index, field))
+    }
+
+    /// Reads a field from every stream of a kind with MediaInfo.
+    fn media_strings(&mut self, stream_kind: StreamKind, field: &str) -> Result<Vec<String>> {
+        (0..self.media_count(stream_kind)?)
+            .map(|index| self.media_string_at(stream_kind, index, field))
+            .collect()
+    }
+
+    /// Counts the streams of a kind with MediaInfo.
+    fn media_count(&mut self, stream_kind: StreamKind) -> Result<usize> {
+        let key = format!("{}/count", stream_kind.name());
+
+        Ok(self
+            .with_media_info(key, |mi| mi.count(stream_kind).to_string())?
+            .parse()?)
+    }
+
+    /// Reads from the item's MediaInfo handle, or from the cache when the value was read
+    /// before under the same key.
+    fn with_media_info<F>(&mut self, key: String, f: F) -> Result<String>
+    where
+        F: FnOnce(&MediaInfo) -> String,
+    {
+        if let Some(value) = self.extracted.media.get(&key) {
+            return Ok(value.clone());
+        }
+
+        let value = f(self.media_info()?);
+        self.extracted.media.insert(key, value.clone());
+        self.extracted_changed = true;
+
+        Ok(value)
+    }
+
+    /// Reads a field with MediaInfo, treating an empty value as missing.
+    fn media_value(&mut self, stream_kind: StreamKind, field: &str) -> Result<String> {
+        let value = self.media_string(stream_kind, field)?;
+
+        if value.is_empty() {
+            Err(ItemError::Failure)
         } else {
-            Err(ItemError::ConvertTime)
+            Ok(value)
+        }
+    }
+}
+
+impl Drop for Item<'_> {
+    /// Keeps the metadata read from the item in the cache for the next run.
+    fn drop(&mut self) {
+        if let (Some(id), Some(cache)) = (self.id, &self.context.cache) {
+            if self.extracted_changed {
+                let extracted = std::mem::take(&mut self.extracted);
+                cache.borrow_mut().insert(self.path(), id, extracted);
+            }
         }
+    }
+}
+
+/// Formats that are the base of other formats, or hold any content, so the extension of a file
+/// in one of them can't be checked.
+fn is_generic(format: FileFormat) -> bool {
+    matches!(
+        format,
+        FileFormat::ArbitraryBinaryData
+            | FileFormat::PlainText
+            | FileFormat::Zip
+            | FileFormat::ExtensibleMarkupLanguage
+            | FileFormat::CompoundFileBinary
+    )
+}
+
+/// Extensions used for the same content, where the one the content is detected as depends on
+/// details like the brand of an MP4 or the doctype of a Matroska file.
+const EXTENSION_ALIASES: [&[&str]; 3] = [
+    &["gz", "tgz"],
+    &["mp4", "m4a", "m4b", "m4v"],
+    &["mkv", "mka", "webm"],
+];
+
+/// Whether a lowercase extension isn't one files of the format are known by.
+fn is_mismatched(format: FileFormat, extension: &str) -> bool {
+    !is_generic(format) && !extension_fits(format, extension)
+}
+
+/// Whether a lowercase extension is one files of the format are known by.
+fn extension_fits(format: FileFormat, extension: &str) -> bool {
+    extension == format.extension()
+        || mime_guess::get_mime_extensions_str(format.media_type())
+            .is_some_and(|extensions| extensions.contains(&extension))
+        || mime_guess::from_ext(extension)
+            .iter()
+            .any(|mime| mime.essence_str() == format.media_type())
+        || EXTENSION_ALIASES
+            .iter()
+            .any(|aliases| aliases.contains(&extension) && aliases.contains(&format.extension()))
+}
+
+/// Greatest common divisor of two numbers.
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
     } else {
-        Err(ItemError::Failure)
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_are_not_mismatched() {
+        assert!(!is_mismatched(
+            FileFormat::JointPhotographicExpertsGroup,
+            "jpg"
+        ));
+        assert!(!is_mismatched(
+            FileFormat::JointPhotographicExpertsGroup,
+            "jpeg"
+        ));
+        assert!(!is_mismatched(FileFormat::Gzip, "gz"));
+        assert!(!is_mismatched(FileFormat::Gzip, "tgz"));
+        assert!(!is_mismatched(FileFormat::AppleItunesAudio, "m4a"));
+        assert!(!is_mismatched(FileFormat::AppleItunesAudio, "mp4"));
+        assert!(!is_mismatched(FileFormat::Mpeg4Part14Video, "m4a"));
+        assert!(!is_mismatched(FileFormat::MatroskaVideo, "webm"));
+        assert!(!is_mismatched(FileFormat::Webm, "mkv"));
+        assert!(!is_mismatched(FileFormat::OfficeOpenXmlDocument, "docx"));
+    }
+
+    #[test]
+    fn other_extensions_are_mismatched() {
+        assert!(is_mismatched(FileFormat::OfficeOpenXmlDocument, "xlsx"));
+        assert!(is_mismatched(FileFormat::OfficeOpenXmlSpreadsheet, "docx"));
+        assert!(is_mismatched(
+            FileFormat::JointPhotographicExpertsGroup,
+            "png"
+        ));
+        assert!(is_mismatched(FileFormat::PortableDocumentFormat, "jpg"));
+        assert!(is_mismatched(FileFormat::Gzip, "zip"));
+        assert!(is_mismatched(FileFormat::MatroskaVideo, "mp4"));
+    }
+
+    #[test]
+    fn generic_formats_are_never_mismatched() {
+        assert!(!is_mismatched(FileFormat::Zip, "docx"));
+        assert!(!is_mismatched(FileFormat::PlainText, "csv"));
+        assert!(!is_mismatched(FileFormat::ArbitraryBinaryData, "jpg"));
     }
 }
@@ -1,10 +1,14 @@
-use crate::{item::Item, parser, AppError, Result};
-use serde::{Deserialize, Serialize};
-use std::{
-    fs,
-    path::{Path, PathBuf},
-    str::FromStr,
+use crate::{
+    action::Action,
+    cache::Cache,
+    condition::Condition,
+    item::Item,
+    needs::Needs,
+    options::{Context, Options},
+    parser, AppError, Result,
 };
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, fs, path::Path};
 
 /// A Job defines the renaming pattern to apply to the source directory.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -12,9 +16,26 @@ pub struct Job {
     name: String,
     source: String,
     destination: Option<String>,
+    /// Where items are moved to, needed when the Job moves items.
+    #[serde(default)]
     pattern: String,
     recursive: Option<bool>,
     watch: Option<bool>,
+    /// Checks items have to pass, all of them, for the actions to run.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    conditions: Vec<Condition>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    actions: Vec<Action>,
+    #[serde(flatten)]
+    options: Options,
 }
 
 /// A list of Job definitions
@@ -24,6 +45,15 @@ pub struct Jobs {
 }
 
 impl Jobs {
+    /// Checks every job in the list.
+    pub fn validate(&self) -> Result<()> {
+        for job in &self.jobs {
+            job.validate()?;
+        }
+
+        Ok(())
+    }
+
     pub fn run_all(&self) -> Result<()> {
         for job in &self.jobs {
             job.run()?;
@@ -50,63 +80,161 @@ impl Job {
             source
         };
 
-        Ok(Self {
+        let job = Self {
             name: name.to_string(),
             destination: Some(destination.to_string()),
             recursive: Some(recursive),
             watch: Some(watch),
             pattern: pattern.to_string(),
             source: source.to_string(),
-        })
+            conditions: Vec::new(),
+            actions: Vec::new(),
+            options: Options::default(),
+        };
+        job.validate()?;
+
+        Ok(job)
     }
 
     pub fn from_file(path: &str) -> Result<Jobs> {
         let file = std::fs::read(path)?;
         let job_list: Jobs = serde_yaml::from_slice(&file)?;
+        job_list.validate()?;
 
         Ok(job_list)
     }
 
+    /// Checks the Job's pattern and options before any item is processed.
+    pub fn validate(&self) -> Result<()> {
+        Context::new(&self.options)?;
+
+        for condition in &self.conditions {
+            condition.validate()?;
+        }
+
+        for action in &self.actions {
+            action.validate()?;
+        }
+
+        if self.moves() {
+            parser::validate_pattern(&self.pattern)?;
+
+            let searches = self
+                .conditions
+                .iter()
+                .any(|condition| matches!(condition, Condition::Contains(_)));
+
+            if !searches && parser::pattern_uses_match(&self.pattern)? {
+                return Err(AppError::MissingSearch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The actions to run on items, moving them when none are set.
+    fn actions(&self) -> &[Action] {
+        if self.actions.is_empty() {
+            &[Action::Move]
+        } else {
+            &self.actions
+        }
+    }
+
+    fn moves(&self) -> bool {
+        self.actions().contains(&Action::Move)
+    }
+
+    /// Works out whether the pattern, conditions or actions read the content of items.
+    fn needs(&self) -> Result<Needs> {
+        let mut needs = if self.moves() {
+            parser::pattern_needs(&self.pattern)?
+        } else {
+            Needs::NONE
+        };
+
+        for condition in &self.conditions {
+            needs |= condition.needs();
+        }
+
+        for action in self.actions() {
+            needs |= action.needs();
+        }
+
+        Ok(needs)
+    }
+
     /// Runs a Job
     pub fn run(&self) -> Result<()> {
-        let base_dest = self.destination.as_ref().unwrap_or(&self.source);
+        let mut context = Context::new(&self.options)?;
+        context.needs = self.needs()?;
+        // Only what's read from the content of items is cached.
+        if context.needs.content {
+            context.cache = Cache::open().map(RefCell::new);
+        }
 
-        process_source(&self.source, self.recursive.unwrap_or_default(), |item| {
-            let pattern = parser::parse_pattern(&self.pattern, item)?;
+        let result = process_source(
+            &self.source,
+            self.recursive.unwrap_or_default(),
+            &context,
+            |item| {
+                if !self.conditions.iter().all(|c| c.holds(item)) {
+                    return Ok(());
+                }
 
-            let mut item_name = "".to_string();
+                for action in self.actions() {
+                    action.apply(self, item)?;
+                }
 
-            if item.is_file() {
-                item_name = item
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-            } else if item.is_dir() {
-                item_name = item.dir_name().unwrap_or_default();
+                Ok(())
+            },
+        );
+
+        // What was read before an error is still worth keeping.
+        if let Some(cache) = context.cache {
+            if let Err(e) = cache.into_inner().save() {
+                eprintln!("warning: couldn't save the metadata cache: {e}");
             }
+        }
+
+        result
+    }
 
-            let dest = format!("{}/{}/{}", base_dest, pattern.to_str().unwrap(), item_name);
+    /// Moves an item to the destination its pattern resolves to.
+    pub(crate) fn move_item(&self, item: &mut Item) -> Result<()> {
+        let base_dest = self.destination.as_ref().unwrap_or(&self.source);
+        let pattern = parser::parse_pattern(&self.pattern, item)?;
+
+        let mut item_name = "".to_string();
+
+        if item.is_file() {
+            item_name = item
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        } else if item.is_dir() {
+            item_name = item.dir_name().unwrap_or_default();
+        }
 
-            //item.move_to(PathBuf::from_str(&dest).unwrap())?;
+        let dest = format!("{}/{}/{}", base_dest, pattern.to_str().unwrap(), item_name);
 
-            println!("{}/{item_name}\n\t\t{dest}", self.source);
+        //item.move_to(PathBuf::from_str(&dest).unwrap())?;
 
-            Ok(())
-        })?;
+        println!("{}/{item_name}\n\t\t{dest}", self.source);
 
         Ok(())
     }
 }
 
 /// Read the list of entries from the source directory and process each one.
-fn process_source<F>(path: &str, recursive: bool, f: F) -> Result<()>
+fn process_source<F>(path: &str, recursive: bool, context: &Context, f: F) -> Result<()>
 where
     F: FnOnce(&mut Item) -> Result<()> + Copy,
 {
     for entry in fs::read_dir(path)? {
         let entry = entry?;
-        let mut item = Item::new(&entry)?;
+        let mut item = Item::new(&entry, context)?;
 
         if recursive {
             if item.is_file() {
@@ -117,6 +245,7 @@ where
                         .to_str()
                         .expect("failed to convert item path to string"),
                     recursive,
+                    context,
                     f,
                 )?;
             }
@@ -140,3 +269,28 @@ fn create_path(path: &str) -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Validates a Job moving items to `{match:1}` with the conditions.
+    fn validate(conditions: &str) -> Result<()> {
+        let job: Job = serde_yaml::from_str(&format!(
+            "{{ name: invoices, source: /tmp, pattern: '/{{match:1}}', conditions: {conditions} }}"
+        ))
+        .unwrap();
+
+        job.validate()
+    }
+
+    #[test]
+    fn match_needs_a_contains_condition() {
+        assert!(validate("[]").is_err_and(|e| matches!(e, AppError::MissingSearch)));
+        assert!(validate("[{ not: { contains: { text: a } } }]")
+            .is_err_and(|e| matches!(e, AppError::MissingSearch)));
+        assert!(validate("[{ any: [{ contains: { text: a } }] }]")
+            .is_err_and(|e| matches!(e, AppError::MissingSearch)));
+        assert!(validate(r"[{ contains: { regex: 'INV-(\d+)' } }]").is_ok());
+    }
+}
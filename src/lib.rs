@@ -1,9 +1,28 @@
 use crate::error::AppError;
 
+mod action;
+mod archive;
+mod audio;
+pub mod cache;
+mod category;
+mod condition;
+mod dates;
+mod dimensions;
+mod directory;
+mod document;
+mod email;
 mod error;
 mod item;
 pub mod job;
 mod mediainfo;
+mod needs;
+pub mod options;
 mod parser;
+mod photo;
+mod search;
+mod tags;
+mod threshold;
+mod users;
+mod xml;
 
 pub type Result<T> = std::result::Result<T, AppError>;
@@ -1,9 +1,68 @@
-use hazel_rs::{job::Job, Result};
+use hazel_rs::{cache::Cache, job::Job, Result};
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    let jobs = Job::from_file("jobs.sample.yaml")?;
+const USAGE: &str = "usage:
+    hazel-rs [--config <jobs.yaml>]
+    hazel-rs cache [info | list | prune | clear]";
 
-    jobs.run_all()?;
+fn main() -> Result<ExitCode> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-    Ok(())
+    match args.as_slice() {
+        [] => run("jobs.sample.yaml")?,
+        ["--config", path] => run(path)?,
+        ["cache", command @ ..] => return cache(command),
+        _ => {
+            eprintln!("{USAGE}");
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(path: &str) -> Result<()> {
+    let jobs = Job::from_file(path)?;
+
+    jobs.run_all()
+}
+
+/// Inspects or prunes the metadata cache.
+fn cache(command: &[&str]) -> Result<ExitCode> {
+    let Some(mut cache) = Cache::open() else {
+        eprintln!("no cache directory, set XDG_CACHE_HOME or HOME");
+        return Ok(ExitCode::FAILURE);
+    };
+
+    match command {
+        [] | ["info"] => {
+            println!("{}", cache.path().display());
+            println!("{} files", cache.len());
+        }
+        ["list"] => {
+            for entry in cache.entries() {
+                println!(
+                    "{}\t{}",
+                    entry.path().display(),
+                    entry.contents().join(", ")
+                );
+            }
+        }
+        ["prune"] => {
+            let removed = cache.prune();
+            cache.save()?;
+            println!("removed {removed} files, {} left", cache.len());
+        }
+        ["clear"] => {
+            cache.clear();
+            cache.save()?;
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
@@ -1,19 +1,54 @@
 use libc::{c_void, size_t, wchar_t};
-use widestring::WideCString;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use widestring::{WideCStr, WideCString, WideChar};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StreamKind {
-    _General = 0,
+    General = 0,
     Video,
-    _Audio,
-    _Text,
-    _Other,
+    Audio,
+    Text,
+    Other,
     Image,
-    _Menu,
+    Menu,
     _Max,
 }
 
+impl StreamKind {
+    /// The stream kinds that appear in a report, in MediaInfo's order.
+    pub(crate) const ALL: [StreamKind; 7] = [
+        Self::General,
+        Self::Video,
+        Self::Audio,
+        Self::Text,
+        Self::Other,
+        Self::Image,
+        Self::Menu,
+    ];
+
+    /// The name MediaInfo uses for this kind of stream.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::General => "General",
+            Self::Video => "Video",
+            Self::Audio => "Audio",
+            Self::Text => "Text",
+            Self::Other => "Other",
+            Self::Image => "Image",
+            Self::Menu => "Menu",
+            Self::_Max => "",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub enum InfoKind {
@@ -28,9 +63,23 @@ pub enum InfoKind {
     _Max,
 }
 
-#[allow(dead_code)]
+/// Whether media files can be read with MediaInfo, either because it's linked or because it
+/// was loaded at runtime. Without it, tokens that need MediaInfo are left out of the
+/// destination, except for image dimensions which are read from the image header.
+#[cfg(feature = "mediainfo-dlopen")]
+pub(crate) fn available() -> bool {
+    dynamic::library().is_some()
+}
+
+#[cfg(not(feature = "mediainfo-dlopen"))]
+pub(crate) fn available() -> bool {
+    cfg!(feature = "mediainfo")
+}
+
+#[cfg(all(feature = "mediainfo", not(feature = "mediainfo-dlopen")))]
 extern "C" {
     fn MediaInfo_New() -> *mut c_void;
+    fn MediaInfo_Delete(handle: *mut c_void);
     fn MediaInfo_Close(handle: *mut c_void);
     fn MediaInfo_Open(handle: *mut c_void, path: *const wchar_t) -> size_t;
     fn MediaInfo_Option(
@@ -39,6 +88,11 @@ extern "C" {
         value: *const wchar_t,
     ) -> *const wchar_t;
     fn MediaInfo_Inform(handle: *mut c_void, reserved: size_t) -> *const wchar_t;
+    fn MediaInfo_Count_Get(
+        handle: *mut c_void,
+        stream_kind: StreamKind,
+        stream_number: size_t,
+    ) -> size_t;
     fn MediaInfo_Get(
         handle: *mut c_void,
         stream_kind: StreamKind,
@@ -49,17 +103,243 @@ extern "C" {
     ) -> *const wchar_t;
 }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+#[cfg(feature = "mediainfo-dlopen")]
+use self::dynamic::*;
+
+#[cfg(not(any(feature = "mediainfo", feature = "mediainfo-dlopen")))]
+use self::unavailable::*;
+
+/// The library loaded at runtime, so the same binary runs with and without libmediainfo
+/// installed. Each function forwards to the symbol of the same name.
+#[cfg(feature = "mediainfo-dlopen")]
+#[allow(non_snake_case)]
+mod dynamic {
+    use super::{InfoKind, StreamKind};
+    use libc::{c_void, size_t, wchar_t};
+    use std::sync::OnceLock;
+
+    #[cfg(target_os = "macos")]
+    const LIBRARY_NAMES: &[&str] = &["libmediainfo.0.dylib", "libmediainfo.dylib"];
+    #[cfg(not(target_os = "macos"))]
+    const LIBRARY_NAMES: &[&str] = &["libmediainfo.so.0", "libmediainfo.so"];
+
+    static LIBRARY: OnceLock<Option<Library>> = OnceLock::new();
+
+    pub(crate) struct Library {
+        // The symbols below point into the library, so it's kept loaded with them.
+        _library: libloading::Library,
+        new: unsafe extern "C" fn() -> *mut c_void,
+        delete: unsafe extern "C" fn(*mut c_void),
+        close: unsafe extern "C" fn(*mut c_void),
+        open: unsafe extern "C" fn(*mut c_void, *const wchar_t) -> size_t,
+        option: unsafe extern "C" fn(*mut c_void, *const wchar_t, *const wchar_t) -> *const wchar_t,
+        inform: unsafe extern "C" fn(*mut c_void, size_t) -> *const wchar_t,
+        count_get: unsafe extern "C" fn(*mut c_void, StreamKind, size_t) -> size_t,
+        get: unsafe extern "C" fn(
+            *mut c_void,
+            StreamKind,
+            size_t,
+            *const wchar_t,
+            InfoKind,
+            InfoKind,
+        ) -> *const wchar_t,
+    }
+
+    impl Library {
+        fn load() -> Result<Self, libloading::Error> {
+            let mut error = None;
+
+            for &name in LIBRARY_NAMES {
+                match unsafe { libloading::Library::new(name) } {
+                    Ok(library) => return unsafe { Self::resolve(library) },
+                    Err(e) => error = Some(e),
+                }
+            }
+
+            Err(error.expect("no library names to load"))
+        }
+
+        /// # Safety
+        ///
+        /// The library has to be libmediainfo, so the symbols have the declared signatures.
+        unsafe fn resolve(library: libloading::Library) -> Result<Self, libloading::Error> {
+            Ok(Self {
+                new: *library.get("MediaInfo_New")?,
+                delete: *library.get("MediaInfo_Delete")?,
+                close: *library.get("MediaInfo_Close")?,
+                open: *library.get("MediaInfo_Open")?,
+                option: *library.get("MediaInfo_Option")?,
+                inform: *library.get("MediaInfo_Inform")?,
+                count_get: *library.get("MediaInfo_Count_Get")?,
+                get: *library.get("MediaInfo_Get")?,
+                _library: library,
+            })
+        }
+    }
+
+    /// Loads the library the first time it's needed, warning once if it can't be found.
+    pub(crate) fn library() -> Option<&'static Library> {
+        LIBRARY
+            .get_or_init(|| match Library::load() {
+                Ok(library) => Some(library),
+                Err(e) => {
+                    // The cause, e.g. the library not being found, is in the source.
+                    let reason = std::error::Error::source(&e)
+                        .map_or(e.to_string(), |source| source.to_string());
+                    eprintln!(
+                        "warning: couldn't load libmediainfo, media tokens are turned off: {reason}"
+                    );
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    pub(crate) unsafe fn MediaInfo_New() -> *mut c_void {
+        match library() {
+            Some(library) => (library.new)(),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    // A handle only exists once the library is loaded, so the functions below can't be called
+    // without it.
+
+    pub(crate) unsafe fn MediaInfo_Delete(handle: *mut c_void) {
+        if let Some(library) = library() {
+            (library.delete)(handle)
+        }
+    }
+
+    pub(crate) unsafe fn MediaInfo_Close(handle: *mut c_void) {
+        if let Some(library) = library() {
+            (library.close)(handle)
+        }
+    }
+
+    pub(crate) unsafe fn MediaInfo_Open(handle: *mut c_void, path: *const wchar_t) -> size_t {
+        library().map_or(0, |library| (library.open)(handle, path))
+    }
+
+    pub(crate) unsafe fn MediaInfo_Option(
+        handle: *mut c_void,
+        parameter: *const wchar_t,
+        value: *const wchar_t,
+    ) -> *const wchar_t {
+        library().map_or(std::ptr::null(), |library| {
+            (library.option)(handle, parameter, value)
+        })
+    }
+
+    pub(crate) unsafe fn MediaInfo_Inform(handle: *mut c_void, reserved: size_t) -> *const wchar_t {
+        library().map_or(std::ptr::null(), |library| {
+            (library.inform)(handle, reserved)
+        })
+    }
+
+    pub(crate) unsafe fn MediaInfo_Count_Get(
+        handle: *mut c_void,
+        stream_kind: StreamKind,
+        stream_number: size_t,
+    ) -> size_t {
+        library().map_or(0, |library| {
+            (library.count_get)(handle, stream_kind, stream_number)
+        })
+    }
+
+    pub(crate) unsafe fn MediaInfo_Get(
+        handle: *mut c_void,
+        stream_kind: StreamKind,
+        stream_number: size_t,
+        parameter: *const wchar_t,
+        info_kind: InfoKind,
+        search_kind: InfoKind,
+    ) -> *const wchar_t {
+        library().map_or(std::ptr::null(), |library| {
+            (library.get)(
+                handle,
+                stream_kind,
+                stream_number,
+                parameter,
+                info_kind,
+                search_kind,
+            )
+        })
+    }
+}
+
+/// Stand-ins for the library when it isn't linked. No handle can be created, so the rest are
+/// never called.
+#[cfg(not(any(feature = "mediainfo", feature = "mediainfo-dlopen")))]
+#[allow(non_snake_case)]
+mod unavailable {
+    use super::{InfoKind, StreamKind};
+    use libc::{c_void, size_t, wchar_t};
+
+    pub(crate) unsafe fn MediaInfo_New() -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    pub(crate) unsafe fn MediaInfo_Delete(_handle: *mut c_void) {}
+
+    pub(crate) unsafe fn MediaInfo_Close(_handle: *mut c_void) {}
+
+    pub(crate) unsafe fn MediaInfo_Open(_handle: *mut c_void, _path: *const wchar_t) -> size_t {
+        0
+    }
+
+    pub(crate) unsafe fn MediaInfo_Option(
+        _handle: *mut c_void,
+        _parameter: *const wchar_t,
+        _value: *const wchar_t,
+    ) -> *const wchar_t {
+        std::ptr::null()
+    }
+
+    pub(crate) unsafe fn MediaInfo_Inform(
+        _handle: *mut c_void,
+        _reserved: size_t,
+    ) -> *const wchar_t {
+        std::ptr::null()
+    }
+
+    pub(crate) unsafe fn MediaInfo_Count_Get(
+        _handle: *mut c_void,
+        _stream_kind: StreamKind,
+        _stream_number: size_t,
+    ) -> size_t {
+        0
+    }
+
+    pub(crate) unsafe fn MediaInfo_Get(
+        _handle: *mut c_void,
+        _stream_kind: StreamKind,
+        _stream_number: size_t,
+        _parameter: *const wchar_t,
+        _info_kind: InfoKind,
+        _search_kind: InfoKind,
+    ) -> *const wchar_t {
+        std::ptr::null()
+    }
+}
+
+/// A MediaInfo handle. The handle is closed and deleted when it's dropped, so it can't be
+/// copied.
+#[derive(Debug)]
 pub struct MediaInfo {
     handle: *mut c_void,
     is_open: bool,
 }
 
 impl MediaInfo {
-    pub(crate) fn new() -> Self {
+    /// Creates a handle, returning `None` if MediaInfo couldn't allocate one.
+    pub(crate) fn new() -> Option<Self> {
         let handle = unsafe { MediaInfo_New() };
 
+        if handle.is_null() {
+            return None;
+        }
+
         let mi = Self {
             handle,
             is_open: false,
@@ -68,14 +348,18 @@ impl MediaInfo {
         //mi.option("Complete", "1");
         mi.option("Output", "JSON");
 
-        mi
+        Some(mi)
     }
 
-    pub(crate) fn open(&self, path: &str) -> bool {
-        let wchar = WideCString::from_str(path).expect("failed to create wchar");
-        let result = unsafe { MediaInfo_Open(self.handle, wchar.as_ptr() as *const i32) };
+    /// Opens a file, closing the file that was open before.
+    pub(crate) fn open(&mut self, path: &Path) -> bool {
+        self.close();
+
+        let path = WideCString::from_os_str_truncate(path.as_os_str());
+        let result = unsafe { MediaInfo_Open(self.handle, path.as_ptr() as *const wchar_t) };
 
-        result == 1
+        self.is_open = result == 1;
+        self.is_open
     }
 
     #[allow(dead_code)]
@@ -83,52 +367,296 @@ impl MediaInfo {
         self.is_open
     }
 
-    pub(crate) fn close(&self) {
-        unsafe {
-            MediaInfo_Close(self.handle);
+    pub(crate) fn close(&mut self) {
+        if self.is_open {
+            unsafe {
+                MediaInfo_Close(self.handle);
+            }
+
+            self.is_open = false;
         }
     }
 
-    pub(crate) fn _inform(&self, info_stream: Option<&str>, parameter: Option<&str>) -> String {
+    /// Builds the report of the open file, in the format set by the `Output` or `Inform`
+    /// options.
+    pub(crate) fn inform(&self, info_stream: Option<&str>, parameter: Option<&str>) -> String {
         if let (Some(p), Some(s)) = (parameter, info_stream) {
             self.option("Inform", &format!("{s};%{p}%"));
         };
 
-        let result = unsafe {
-            WideCString::from_ptr_str(MediaInfo_Inform(self.handle, 0 as size_t) as *const u32)
-        };
+        unsafe { from_wide(MediaInfo_Inform(self.handle, 0 as size_t)) }
+    }
+
+    /// Reads the full report of the open file.
+    pub(crate) fn report(&self) -> Option<MediaReport> {
+        self.option("Inform", "");
 
-        result.to_string_lossy()
+        MediaReport::parse(&self.inform(None, None))
     }
 
-    pub(crate) fn get_string(&self, stream_kind: StreamKind, field: &str) -> String {
-        let wchar = WideCString::from_str(field).expect("failed to create wchar");
-        let result = unsafe {
-            WideCString::from_ptr_str(MediaInfo_Get(
+    /// Reads a field from the stream of a kind at an index, starting at 0.
+    pub(crate) fn get(&self, stream_kind: StreamKind, stream_number: usize, field: &str) -> String {
+        let Ok(field) = WideCString::from_str(field) else {
+            return String::new();
+        };
+
+        unsafe {
+            from_wide(MediaInfo_Get(
                 self.handle,
                 stream_kind,
-                0,
-                wchar.as_ptr() as *const i32,
+                stream_number,
+                field.as_ptr() as *const wchar_t,
                 InfoKind::Text,
                 InfoKind::Name,
-            ) as *const u32)
-        };
+            ))
+        }
+    }
 
-        result.to_string_lossy()
+    /// Counts the streams of a kind.
+    pub(crate) fn count(&self, stream_kind: StreamKind) -> usize {
+        // A stream number of -1 asks for the number of streams instead of their fields.
+        unsafe { MediaInfo_Count_Get(self.handle, stream_kind, usize::MAX) }
     }
 
     pub(crate) fn option(&self, param: &str, value: &str) -> String {
-        let result = unsafe {
-            let param = WideCString::from_str(param).expect("failed to create cpath");
-            let value = WideCString::from_str(value).expect("failed to create cpath");
+        let (Ok(param), Ok(value)) = (WideCString::from_str(param), WideCString::from_str(value))
+        else {
+            return String::new();
+        };
 
-            WideCString::from_ptr_str(MediaInfo_Option(
+        unsafe {
+            from_wide(MediaInfo_Option(
                 self.handle,
-                param.as_ptr() as *const i32,
-                value.as_ptr() as *const i32,
-            ) as *const u32)
-        };
+                param.as_ptr() as *const wchar_t,
+                value.as_ptr() as *const wchar_t,
+            ))
+        }
+    }
+}
+
+impl Drop for MediaInfo {
+    fn drop(&mut self) {
+        self.close();
+
+        unsafe {
+            MediaInfo_Delete(self.handle);
+        }
+    }
+}
+
+/// Copies a string returned by MediaInfo, which stays owned by the handle.
+///
+/// # Safety
+///
+/// The pointer has to be null or point to a nul-terminated wide string.
+unsafe fn from_wide(ptr: *const wchar_t) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    WideCStr::from_ptr_str(ptr as *const WideChar).to_string_lossy()
+}
+
+/// The names of the fields MediaInfo knows for each kind of stream, used to validate patterns.
+pub(crate) fn parameters() -> BTreeMap<String, Vec<String>> {
+    let list = match MediaInfo::new() {
+        Some(mi) => mi.option("Info_Parameters", ""),
+        None => String::new(),
+    };
+
+    let mut parameters: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut stream = None;
+
+    for line in list.lines() {
+        match line.split_once(':') {
+            Some((field, _)) if stream.is_some() => {
+                if let Some(fields) = stream.as_ref().and_then(|s| parameters.get_mut(s)) {
+                    fields.push(field.trim().to_string());
+                }
+            }
+            None if !line.trim().is_empty() => {
+                let name = line.trim().to_string();
+                parameters.entry(name.clone()).or_default();
+                stream = Some(name);
+            }
+            _ => {}
+        }
+    }
+
+    parameters
+}
+
+/// The JSON report MediaInfo produces for a file, split into its streams.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct MediaReport {
+    pub(crate) general: Vec<Stream>,
+    pub(crate) video: Vec<Stream>,
+    pub(crate) audio: Vec<Stream>,
+    pub(crate) text: Vec<Stream>,
+    pub(crate) other: Vec<Stream>,
+    pub(crate) image: Vec<Stream>,
+    pub(crate) menu: Vec<Stream>,
+}
+
+/// The fields of a single stream in a report.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Stream {
+    pub(crate) fields: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RawReport {
+    media: Option<RawMedia>,
+}
+
+#[derive(Deserialize)]
+struct RawMedia {
+    #[serde(default)]
+    track: Vec<RawTrack>,
+}
+
+#[derive(Deserialize)]
+struct RawTrack {
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(flatten)]
+    fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl MediaReport {
+    pub(crate) fn parse(json: &str) -> Option<Self> {
+        let raw: RawReport = serde_json::from_str(json).ok()?;
+        let mut report = Self::default();
+
+        for track in raw.media?.track {
+            let Some(kind) = StreamKind::from_name(&track.kind) else {
+                continue;
+            };
+
+            let mut fields = BTreeMap::new();
+            for (name, value) in track.fields {
+                match value {
+                    serde_json::Value::String(value) => {
+                        fields.insert(name, value);
+                    }
+                    // Fields MediaInfo doesn't have a name for are nested under `extra`, and
+                    // are read as `extra.Name`.
+                    serde_json::Value::Object(extra) if name == "extra" => {
+                        for (name, value) in extra {
+                            if let serde_json::Value::String(value) = value {
+                                fields.insert(format!("extra.{name}"), value);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            report.streams_mut(kind).push(Stream { fields });
+        }
+
+        Some(report)
+    }
+
+    pub(crate) fn streams(&self, kind: StreamKind) -> &[Stream] {
+        match kind {
+            StreamKind::General => &self.general,
+            StreamKind::Video => &self.video,
+            StreamKind::Audio => &self.audio,
+            StreamKind::Text => &self.text,
+            StreamKind::Other => &self.other,
+            StreamKind::Image => &self.image,
+            StreamKind::Menu => &self.menu,
+            StreamKind::_Max => &[],
+        }
+    }
+
+    fn streams_mut(&mut self, kind: StreamKind) -> &mut Vec<Stream> {
+        match kind {
+            StreamKind::General => &mut self.general,
+            StreamKind::Video => &mut self.video,
+            StreamKind::Audio => &mut self.audio,
+            StreamKind::Text => &mut self.text,
+            StreamKind::Image => &mut self.image,
+            StreamKind::Menu => &mut self.menu,
+            StreamKind::Other | StreamKind::_Max => &mut self.other,
+        }
+    }
+
+    /// Reads a field from the stream of a kind at an index.
+    pub(crate) fn field(&self, kind: StreamKind, index: usize, field: &str) -> Option<&str> {
+        self.streams(kind)
+            .get(index)?
+            .fields
+            .get(field)
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = r#"{
+  "creatingLibrary": {"name": "MediaLib", "version": "24.01", "url": "https://mediaarea.net/MediaInfo"},
+  "media": {
+    "@ref": "movie.mkv",
+    "track": [
+      {"@type": "General", "Format": "Matroska", "Duration": "5400.000", "extra": {"ENCODER": "Lavf60"}},
+      {"@type": "Video", "@typeorder": "1", "Format": "AVC", "Width": "1920", "BitRate": "8000000"},
+      {"@type": "Video", "@typeorder": "2", "Format": "HEVC", "Width": "3840", "extra": {"HDR_Format": "Dolby Vision", "Levels": {"Nested": "1"}}},
+      {"@type": "Audio", "Language": "en", "Channels": "6", "Forced": ["No"]},
+      {"@type": "Text", "Language": "fr", "Forced": "Yes"},
+      {"@type": "Unknown", "Format": "Data"}
+    ]
+  }
+}"#;
+
+    #[test]
+    fn streams_are_read_in_order() {
+        let report = MediaReport::parse(REPORT).unwrap();
+
+        assert_eq!(report.general.len(), 1);
+        assert_eq!(report.video.len(), 2);
+        assert_eq!(report.audio.len(), 1);
+        assert_eq!(report.text.len(), 1);
+        assert!(report.image.is_empty());
+
+        assert_eq!(
+            report.field(StreamKind::General, 0, "Format"),
+            Some("Matroska")
+        );
+        assert_eq!(report.field(StreamKind::Video, 0, "Format"), Some("AVC"));
+        assert_eq!(report.field(StreamKind::Video, 1, "Width"), Some("3840"));
+        assert_eq!(report.field(StreamKind::Video, 2, "Width"), None);
+        assert_eq!(report.field(StreamKind::Text, 0, "Forced"), Some("Yes"));
+        // Only text values are kept.
+        assert_eq!(report.field(StreamKind::Audio, 0, "Forced"), None);
+    }
+
+    #[test]
+    fn extra_fields_are_flattened() {
+        let report = MediaReport::parse(REPORT).unwrap();
+
+        assert_eq!(
+            report.field(StreamKind::General, 0, "extra.ENCODER"),
+            Some("Lavf60")
+        );
+        assert_eq!(
+            report.field(StreamKind::Video, 1, "extra.HDR_Format"),
+            Some("Dolby Vision")
+        );
+        assert_eq!(report.field(StreamKind::Video, 0, "extra.HDR_Format"), None);
+        assert_eq!(report.field(StreamKind::Video, 1, "extra.Levels"), None);
+        assert_eq!(report.field(StreamKind::Video, 1, "extra"), None);
+    }
+
+    #[test]
+    fn reports_without_media_are_rejected() {
+        assert!(MediaReport::parse(r#"{"creatingLibrary": {}}"#).is_none());
+        assert!(MediaReport::parse("not json").is_none());
 
-        result.to_string_lossy()
+        let empty = MediaReport::parse(r#"{"media": {"@ref": "empty"}}"#).unwrap();
+        assert!(empty.general.is_empty());
     }
 }
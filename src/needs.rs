@@ -0,0 +1,33 @@
+use std::ops::{BitOr, BitOrAssign};
+
+/// Whether a Job reads metadata from the content of its items, worked out from its pattern,
+/// conditions and actions. This isn't a list of what's loaded: items read each kind of
+/// metadata lazily, when it's first used, whatever the Job needs. It only decides whether items
+/// are looked up in the cache, which holds what's read from content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Needs {
+    /// Metadata read from the content of files, e.g. their format, EXIF or MediaInfo fields.
+    pub(crate) content: bool,
+}
+
+impl Needs {
+    pub(crate) const NONE: Self = Self { content: false };
+
+    pub(crate) const CONTENT: Self = Self { content: true };
+}
+
+impl BitOr for Needs {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            content: self.content || other.content,
+        }
+    }
+}
+
+impl BitOrAssign for Needs {
+    fn bitor_assign(&mut self, other: Self) {
+        *self = *self | other;
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{
+    cache::Cache,
+    category::Categories,
+    dates::{FilenameDates, Timezone},
+    error::AppError,
+    needs::Needs,
+    parser::Specifier,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// Optional settings of a Job.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Options {
+    dates: DateOptions,
+    /// Categories for `{kind}` and `{category}`, tried in order.
+    categories: Vec<Category>,
+}
+
+/// Settings for date tokens.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct DateOptions {
+    /// Regexes that read a date from a file name, tried before the built-in formats. They need
+    /// `year`, `month` and `day` groups and can have `hour`, `minute` and `second` groups.
+    formats: Vec<String>,
+    /// The filesystem date (`created`, `modified`, `changed` or `accessed`) used when a date
+    /// can't be read from the item itself.
+    fallback: Option<String>,
+    /// The filesystem date (`modified`, `changed` or `accessed`) used for `created` on file
+    /// systems that don't record when files were created.
+    created_fallback: Option<String>,
+    /// The timezone of filesystem dates: `local` (the default), `utc` or an offset like
+    /// `+02:00`.
+    timezone: Option<String>,
+}
+
+/// A category of items, which items are in when any of its kinds, extensions, mime types or
+/// formats match.
+///
+/// ```yaml
+/// categories:
+///   - name: raw-photos
+///     extensions: [cr2, nef, arw, dng]
+///   - name: installers
+///     kinds: [executable, package]
+///     mimes: ["application/x-apple-diskimage"]
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Category {
+    pub(crate) name: String,
+    /// Kinds like `image` or `executable`, as `{kind}` writes them.
+    pub(crate) kinds: Vec<String>,
+    pub(crate) extensions: Vec<String>,
+    /// Mime types, which can have `*` wildcards, e.g. `text/*`.
+    pub(crate) mimes: Vec<String>,
+    /// Names of formats, e.g. `Portable Network Graphics` or its short name `PNG`.
+    pub(crate) formats: Vec<String>,
+}
+
+/// The settings of a Job, prepared once before its items are processed.
+#[derive(Debug)]
+pub(crate) struct Context {
+    pub(crate) filename_dates: FilenameDates,
+    pub(crate) date_fallback: Option<Specifier>,
+    pub(crate) created_fallback: Option<Specifier>,
+    pub(crate) timezone: Timezone,
+    pub(crate) categories: Categories,
+    pub(crate) needs: Needs,
+    pub(crate) cache: Option<RefCell<Cache>>,
+}
+
+impl Context {
+    pub(crate) fn new(options: &Options) -> Result<Self> {
+        let dates = &options.dates;
+        let timezone = match &dates.timezone {
+            Some(timezone) => Timezone::new(timezone)?,
+            None => Timezone::default(),
+        };
+
+        Ok(Self {
+            filename_dates: FilenameDates::new(&dates.formats)?,
+            date_fallback: fallback(
+                &dates.fallback,
+                &[
+                    Specifier::Created,
+                    Specifier::Modified,
+                    Specifier::Changed,
+                    Specifier::Accessed,
+                ],
+            )?,
+            created_fallback: fallback(
+                &dates.created_fallback,
+                &[Specifier::Modified, Specifier::Changed, Specifier::Accessed],
+            )?,
+            timezone,
+            categories: Categories::new(&options.categories)?,
+            needs: Needs::default(),
+            cache: None,
+        })
+    }
+}
+
+/// Reads a fallback date option, which has to be one of the allowed filesystem dates.
+fn fallback(fallback: &Option<String>, allowed: &[Specifier]) -> Result<Option<Specifier>> {
+    let Some(fallback) = fallback else {
+        return Ok(None);
+    };
+
+    let specifier = Specifier::from(fallback.as_str());
+
+    if allowed.contains(&specifier) {
+        Ok(Some(specifier))
+    } else {
+        Err(AppError::InvalidFallback {
+            fallback: fallback.to_string(),
+        })
+    }
+}
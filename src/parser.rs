@@ -1,225 +1,769 @@
+use crate::category;
+use crate::email::EmailInfo;
 use crate::item::Item;
+use crate::mediainfo::{self, StreamKind};
+use crate::needs::Needs;
+use crate::threshold::{
+    self, Threshold, Unit, BITRATE_UNITS, COUNT_UNITS, DURATION_UNITS, FRAME_RATE_UNITS,
+    MEGAPIXEL_UNITS, PIXEL_UNITS, SIZE_UNITS,
+};
 use crate::{error::AppError, Result};
-use chrono::Datelike;
+use chrono::format::{Item as FormatItem, StrftimeItems};
+use chrono::{Datelike, NaiveDateTime};
 use file_format::Kind;
 use fs_extra::dir::CopyOptions;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::Parser;
-use std::{fs::DirEntry, path::PathBuf};
+use std::{collections::BTreeMap, fmt::Write, fs::DirEntry, path::PathBuf};
 
 #[derive(Parser)]
 #[grammar = "pathspec.pest"]
-struct TokenParser;
+pub(crate) struct TokenParser;
 
 /// Replaces tokens in the Job's pattern.
 pub fn parse_pattern(pattern: &str, item: &mut Item) -> Result<PathBuf> {
     process_items(pattern, item, |variable, component, item| {
-        let mut tokens = variable.into_inner();
+        let variable = Variable::parse(variable)?;
 
-        process_variables(&mut tokens, item, component)?;
+        process_variables(variable, item, component)?;
 
         Ok(())
     })
 }
 
-/// Replaces variables in the pattern with values.
-fn process_variables(
-    tokens: &mut Pairs<Rule>,
-    item: &mut Item,
-    component: &mut Vec<String>,
-) -> Result<()> {
-    // Get the first of 3 fields in a variable (token:specifier:modifier)
-    if let Some(token) = tokens.next() {
+/// Checks that a pattern can be parsed and that every variable in it is valid, so mistakes
+/// are reported when the job is loaded instead of for each item.
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    let parsed = parse(pattern)?;
+    let mut media_parameters = None;
+
+    for p in parsed.flatten() {
+        if p.as_rule() == Rule::variable {
+            let text = p.as_str();
+            let variable = Variable::parse(p)?;
+
+            if variable.token.needs_media_info() && !mediainfo::available() {
+                eprintln!(
+                    "warning: {text} needs MediaInfo, which isn't available, so it's left out"
+                );
+            }
+
+            if let Some(format) = variable.format {
+                format_date(&NaiveDateTime::default(), format)?;
+            }
+
+            if let Some(field) = &variable.media_field {
+                let parameters = mediainfo::available()
+                    .then(|| &*media_parameters.get_or_insert_with(mediainfo::parameters));
+                field.validate(parameters)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Works out whether a pattern reads the content of items.
+pub(crate) fn pattern_needs(pattern: &str) -> Result<Needs> {
+    let mut needs = Needs::NONE;
+
+    for p in parse(pattern)?.flatten() {
+        if p.as_rule() == Rule::variable {
+            let variable = Variable::parse(p)?;
+            needs |= variable.token.needs(variable.specifier.as_ref());
+        }
+    }
+
+    Ok(needs)
+}
+
+/// Whether a pattern writes the match of a `contains` condition.
+pub(crate) fn pattern_uses_match(pattern: &str) -> Result<bool> {
+    for p in parse(pattern)?.flatten() {
+        if p.as_rule() == Rule::variable && matches!(Variable::parse(p)?.token, Token::Match) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn parse(pattern: &str) -> Result<Pairs<'_, Rule>> {
+    TokenParser::parse(Rule::path, pattern).map_err(|_| AppError::InvalidPattern {
+        pattern: pattern.to_string(),
+    })
+}
+
+/// A field of a MediaInfo stream in a pattern, e.g. `Audio[1].Language`.
+struct MediaField {
+    text: String,
+    stream: StreamKind,
+    index: usize,
+    name: String,
+}
+
+impl MediaField {
+    fn parse(field: Pair<Rule>) -> Result<Self> {
+        let text = field.as_str();
+        let mut stream = None;
+        let mut index = 0;
+        let mut name = String::new();
+
+        for f in field.into_inner() {
+            match f.as_rule() {
+                Rule::stream => stream = StreamKind::from_name(f.as_str()),
+                Rule::stream_index => index = f.as_str().parse().unwrap_or_default(),
+                Rule::field_name => name = f.as_str().to_string(),
+                _ => {}
+            }
+        }
+
+        match stream {
+            Some(stream) => Ok(Self {
+                text: text.to_string(),
+                stream,
+                index,
+                name,
+            }),
+            None => Err(AppError::UnknownMediaField {
+                field: text.to_string(),
+            }),
+        }
+    }
+
+    /// Checks that the field can be in the JSON report. Variants like `BitRate/String` are only
+    /// in the text report, fields MediaInfo has no name for are under `extra.`, and the others
+    /// have to be ones MediaInfo knows for the stream, when MediaInfo lists them.
+    fn validate(&self, parameters: Option<&BTreeMap<String, Vec<String>>>) -> Result<()> {
+        let known = match parameters.and_then(|p| p.get(self.stream.name())) {
+            _ if self.name.contains('/') => false,
+            _ if self.name.starts_with("extra.") => true,
+            Some(fields) => fields.contains(&self.name),
+            None => true,
+        };
+
+        match known {
+            true => Ok(()),
+            false => Err(AppError::UnknownMediaField {
+                field: format!("{}.{}", self.stream.name(), self.name),
+            }),
+        }
+    }
+}
+
+/// The fields of a variable in the pattern (`{token[thresholds]:specifier:modifier}`).
+struct Variable<'p> {
+    token: Token,
+    specifier: Option<Specifier>,
+    media_field: Option<MediaField>,
+    /// The group of a regex match, for `{match:1}`.
+    group: Option<usize>,
+    format: Option<&'p str>,
+    _modifier: Option<Modifier>,
+    thresholds: Vec<Threshold>,
+}
+
+impl<'p> Variable<'p> {
+    /// Reads the fields of a variable and validates them against its token.
+    fn parse(variable: Pair<'p, Rule>) -> Result<Self> {
+        let mut tokens = variable.into_inner();
+
+        let (name, token): (&str, Token) = match tokens.next() {
+            Some(token) => (token.as_str(), token.as_str().into()),
+            None => return Err(AppError::UnkownToken),
+        };
+
+        let mut specifier_name = "";
         let mut specifier: Option<Specifier> = None;
+        let mut media_field: Option<MediaField> = None;
+        let mut group: Option<(&str, usize)> = None;
         let mut _modifier: Option<Modifier> = None;
-        let mut thresholds: Vec<Pair<'_, Rule>> = Vec::new();
-
-        // Check the next two fields for either specifier or modifier.
-        // Both types are checked because they are optional.
-        for _ in 0..2 {
-            if let Some(token) = tokens.next() {
-                if token.as_rule() == Rule::specifier {
-                    specifier = Some(token.as_str().into());
-                } else if token.as_rule() == Rule::modifier {
-                    _modifier = Some(token.as_str().into());
-                } else if token.as_rule() == Rule::thresholds {
-                    for t in token.into_inner() {
+        let mut format: Option<&str> = None;
+        let mut thresholds: Vec<Threshold> = Vec::new();
+
+        // Check the remaining fields for thresholds, specifier, format or modifiers.
+        // All of them are checked because they are optional.
+        for field in tokens {
+            match field.as_rule() {
+                Rule::specifier => {
+                    specifier_name = field.as_str();
+                    specifier = Some(field.as_str().into());
+                }
+                Rule::media_field => media_field = Some(MediaField::parse(field)?),
+                Rule::match_group => {
+                    let text = field.as_str();
+                    let number = text.parse().map_err(|_| AppError::InvalidSpecifier {
+                        token: name.to_string(),
+                        specifier: text.to_string(),
+                    })?;
+
+                    group = Some((text, number));
+                }
+                Rule::format => format = Some(field.as_str()),
+                Rule::modifiers => {
+                    if let Some(modifier) = field.into_inner().next() {
+                        _modifier = Some(modifier.as_str().into());
+                    }
+                }
+                Rule::thresholds => {
+                    for t in field.into_inner() {
                         if let Rule::threshold = t.as_rule() {
-                            thresholds.push(t);
+                            let text = t.as_str();
+
+                            match Threshold::parse(t, token.units()) {
+                                Some(threshold) => thresholds.push(threshold),
+                                None => {
+                                    return Err(AppError::InvalidThreshold {
+                                        token: name.to_string(),
+                                        threshold: text.to_string(),
+                                    })
+                                }
+                            }
                         };
                     }
                 }
-            };
+                _ => {}
+            }
         }
 
-        match token.as_str().into() {
-            Token::Year => {
-                if let Some(specifier) = specifier {
-                    let time = item.datetime(specifier)?;
-                    component.push(time.year().to_string());
-                }
-            }
-            Token::Month => {
-                if let Some(specifier) = specifier {
-                    let time = item.datetime(specifier)?;
-                    component.push(time.month().to_string());
-                }
+        if let Token::Unknown = token {
+            return Err(AppError::UnkownToken);
+        }
+
+        if let Some(specifier) = &specifier {
+            if !token.accepts(specifier) {
+                return Err(AppError::InvalidSpecifier {
+                    token: name.to_string(),
+                    specifier: specifier_name.to_string(),
+                });
             }
-            Token::Day => {
-                if let Some(specifier) = specifier {
-                    let time = item.datetime(specifier)?;
-                    component.push(time.day().to_string());
-                }
+        }
+
+        // Only dates are written with a format.
+        if let (Some(format), false) = (format, matches!(token, Token::Date)) {
+            return Err(AppError::InvalidFormat {
+                token: name.to_string(),
+                format: format.to_string(),
+            });
+        }
+
+        // Media fields belong to the media token, which can't be used without one.
+        match (&media_field, &token) {
+            (None, Token::Media) => return Err(AppError::MissingMediaField),
+            (Some(field), token) if !matches!(token, Token::Media) => {
+                return Err(AppError::InvalidSpecifier {
+                    token: name.to_string(),
+                    specifier: field.text.clone(),
+                })
             }
-            Token::MimeType => {
-                if item.is_file() {
-                    let mime = mime_guess::from_path(item.path());
-                    let first = mime.first_or_text_plain();
+            _ => {}
+        }
 
-                    component.push(first.type_().to_string());
-                    component.push("/".to_string());
-                    component.push(first.subtype().to_string());
-                }
+        // Match groups belong to the match token.
+        if let Some((text, _)) = group {
+            if !matches!(token, Token::Match) {
+                return Err(AppError::InvalidSpecifier {
+                    token: name.to_string(),
+                    specifier: text.to_string(),
+                });
             }
-            Token::Extension => {
-                if item.is_file() {
-                    if let Some(ext) = item.path().extension() {
-                        component.push(ext.to_string_lossy().to_string());
-                    }
-                }
+        }
+
+        Ok(Self {
+            token,
+            specifier,
+            media_field,
+            group: group.map(|(_, number)| number),
+            format,
+            _modifier,
+            thresholds,
+        })
+    }
+}
+
+/// The format used by `{date}` when the pattern doesn't provide one.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The directory segments built from one component of the pattern.
+///
+/// Text and most variables extend the current segment. A variable can also produce several
+/// segments (e.g. `{mime}` => `image/png`), which splits the component into nested directories.
+#[derive(Debug, Default)]
+pub(crate) struct Component {
+    segments: Vec<String>,
+    values: usize,
+}
+
+impl Component {
+    /// Appends text from the pattern to the current segment.
+    fn push_text(&mut self, text: &str) {
+        self.current().push_str(text);
+        self.values += 1;
+    }
+
+    /// Appends a value produced by a variable to the current segment.
+    pub(crate) fn push<S: AsRef<str>>(&mut self, value: S) {
+        self.push_segments([value]);
+    }
+
+    /// Appends the values produced by a variable as separate directory segments. The first
+    /// value continues the current segment and every following value starts a new one.
+    ///
+    /// Each value is sanitized on its own and the variable is ignored if any of them ends up
+    /// empty, which drops the whole component.
+    pub(crate) fn push_segments<I, S>(&mut self, values: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let values: Vec<String> = values
+            .into_iter()
+            .map(|v| sanitize_segment(v.as_ref()))
+            .collect();
+
+        if values.is_empty() || values.iter().any(String::is_empty) {
+            return;
+        }
+
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.segments.push(String::new());
             }
-            Token::Size => {
-                if item.is_file() {
-                    let mut c = "".to_string();
+            self.current().push_str(value);
+        }
 
-                    for t in thresholds {
-                        let name = t.as_str();
+        self.values += 1;
+    }
 
-                        let mut over_under = "";
-                        let mut mult = 0;
-                        let mut thresh = 0_u32;
+    fn current(&mut self) -> &mut String {
+        if self.segments.is_empty() {
+            self.segments.push(String::new());
+        }
 
-                        for t in t.into_inner() {
-                            match t.as_rule() {
-                                Rule::gt => {
-                                    over_under = "over";
-                                }
-                                Rule::lt => {
-                                    over_under = "under";
-                                }
-                                Rule::threshold_size => {
-                                    mult = match t.as_str() {
-                                        "B" => 1,
-                                        "K" => 1024,
-                                        "M" => 1024 * 1024,
-                                        "G" => 1024 * 1024 * 1024,
-                                        _ => 0,
-                                    };
-                                }
-                                Rule::threshold_amount => {
-                                    thresh = t.as_str().parse().expect("error parsing");
-                                }
-                                _ => {}
-                            };
+        self.segments.last_mut().expect("component has a segment")
+    }
+}
 
-                            let size = item.size() as u32;
+/// Makes a value produced by a variable safe to use as a single directory name.
+fn sanitize_segment(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '-',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
 
-                            match size.cmp(&(thresh * mult)) {
-                                std::cmp::Ordering::Less => {
-                                    if over_under == "under" {
-                                        c = name.to_string();
-                                        break;
-                                    }
-                                }
-                                std::cmp::Ordering::Equal => {
-                                    c = name.to_string();
-                                }
-                                std::cmp::Ordering::Greater => {
-                                    if over_under == "over" {
-                                        c = name.to_string();
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
+    match value.trim() {
+        "." | ".." => String::new(),
+        value => value.to_string(),
+    }
+}
 
-                    if !c.is_empty() {
-                        component.push(c);
-                    }
-                } else {
-                    println!("**** DIR SIZE: {}", item.size());
+/// Formats a date with a strftime-style format, returning an error if the format is invalid.
+fn format_date(date: &NaiveDateTime, format: &str) -> Result<String> {
+    let items: Vec<FormatItem> = StrftimeItems::new(format).collect();
+
+    if items.contains(&FormatItem::Error) {
+        return Err(AppError::InvalidDateFormat {
+            format: format.to_string(),
+        });
+    }
+
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format_with_items(items.into_iter())).map_err(|_| {
+        AppError::InvalidDateFormat {
+            format: format.to_string(),
+        }
+    })?;
+
+    Ok(formatted)
+}
+
+/// Replaces variables in the pattern with values.
+fn process_variables(variable: Variable, item: &mut Item, component: &mut Component) -> Result<()> {
+    let Variable {
+        token,
+        specifier,
+        media_field,
+        group,
+        format,
+        thresholds,
+        ..
+    } = variable;
+
+    match token {
+        Token::Year => {
+            if let Some(Ok(time)) = specifier.map(|s| item.datetime(s)) {
+                component.push(time.year().to_string());
+            }
+        }
+        Token::Month => {
+            if let Some(Ok(time)) = specifier.map(|s| item.datetime(s)) {
+                component.push(time.month().to_string());
+            }
+        }
+        Token::Day => {
+            if let Some(Ok(time)) = specifier.map(|s| item.datetime(s)) {
+                component.push(time.day().to_string());
+            }
+        }
+        Token::Date => {
+            if let Some(Ok(time)) = specifier.map(|s| item.datetime(s)) {
+                let date = format_date(&time, format.unwrap_or(DEFAULT_DATE_FORMAT))?;
+
+                // A `//` or a trailing `/` in the format would give empty directories.
+                component.push_segments(date.split('/').filter(|part| !part.is_empty()));
+            }
+        }
+        Token::MimeType => {
+            if let Some(mime) = item.mime() {
+                match specifier {
+                    Some(Specifier::Type) => component.push(mime.type_()),
+                    Some(Specifier::Subtype) => component.push(mime.subtype()),
+                    _ => component.push_segments([mime.type_(), mime.subtype()]),
                 }
             }
-            Token::Kind => {
-                if item.is_file() {
-                    if let Some(kind) = item.kind() {
-                        match kind {
-                            Kind::Application => {
-                                let guess = mime_guess::from_path(item.path());
-
-                                if let Some(first) = guess.first() {
-                                    for p in first.params() {
-                                        println!("{p:?}");
-                                    }
-                                    component.push(first.type_().to_string());
-                                }
+        }
+        Token::Extension => {
+            if item.is_file() {
+                if let Some(ext) = item.path().extension() {
+                    component.push(ext.to_string_lossy());
+                }
+            }
+        }
+        Token::TrueExtension => {
+            if let Some(extension) = item.true_extension() {
+                component.push(extension);
+            }
+        }
+        Token::Size => {
+            if let Ok(size) = item.total_size() {
+                push_measurement(component, size as f64, &thresholds, |size| size.to_string());
+            }
+        }
+        Token::Count => {
+            if let Ok(count) = item.count() {
+                push_measurement(component, count as f64, &thresholds, |c| c.to_string());
+            }
+        }
+        Token::Kind => {
+            // The Job's categories come first, then the kind of the content.
+            if let Some(category) = item.category() {
+                component.push(category);
+            } else if item.is_file() {
+                if let Some(kind) = item.kind() {
+                    match kind {
+                        // Content that's only known to be binary is named by its mime type.
+                        Kind::Application => {
+                            if let Some(mime) = mime_guess::from_path(item.path()).first() {
+                                component.push(mime.type_());
                             }
-                            Kind::Audio => component.push("audio".to_string()),
-                            Kind::Font => component.push("font".to_string()),
-                            Kind::Image => component.push("image".to_string()),
-                            Kind::Model => component.push("model".to_string()),
-                            Kind::Text => component.push("text".to_string()),
-                            Kind::Video => component.push("video".to_string()),
-                            Kind::Archive => component.push("archive".to_string()),
-                            Kind::Book => component.push("book".to_string()),
-                            Kind::Certificate => component.push("certificate".to_string()),
-                            Kind::Compression => component.push("compression".to_string()),
-                            Kind::Disk => component.push("disk".to_string()),
-                            Kind::Document => component.push("document".to_string()),
-                            Kind::Executable => component.push("executable".to_string()),
-                            Kind::Geospatial => component.push("geospatial".to_string()),
-                            Kind::Package => component.push("package".to_string()),
-                            Kind::Playlist => component.push("playlist".to_string()),
-                            Kind::Rom => component.push("rom".to_string()),
-                            Kind::Subtitle => component.push("subtitle".to_string()),
                         }
+                        kind => component.push(category::kind_name(kind)),
                     }
-                } else {
-                    component.push("directory".to_string());
                 }
-            }
-            Token::Width => {
-                if let Ok(width) = &item.width() {
-                    component.push(width.to_string())
+            } else {
+                // Directories are classified by what most of their files are.
+                match item.dominant_kind() {
+                    Some(kind) => component.push(category::kind_name(kind)),
+                    None => component.push("directory"),
                 }
             }
-            Token::Height => {
-                if let Ok(height) = &item.height() {
-                    component.push(height.to_string())
+        }
+        Token::ArchiveCount => {
+            if let Ok(count) = item.archive_count() {
+                push_measurement(component, count as f64, &thresholds, |c| c.to_string());
+            }
+        }
+        Token::ArchiveKind => {
+            if let Some(kind) = item.archive_kind() {
+                component.push(kind);
+            }
+        }
+        Token::Match => {
+            if let Some(text) = item.capture(group.unwrap_or(0)) {
+                component.push(text);
+            }
+        }
+        Token::Category => {
+            if let Some(category) = item.category() {
+                component.push(category);
+            }
+        }
+        Token::Width => {
+            if let Ok(width) = item.width() {
+                push_measurement(component, width as f64, &thresholds, |w| w.to_string());
+            }
+        }
+        Token::Height => {
+            if let Ok(height) = item.height() {
+                push_measurement(component, height as f64, &thresholds, |h| h.to_string());
+            }
+        }
+        Token::Orientation => {
+            if let Ok(orientation) = item.orientation() {
+                component.push(orientation.as_str());
+            }
+        }
+        Token::Aspect => {
+            if let Ok(aspect) = item.aspect_ratio() {
+                component.push(aspect);
+            }
+        }
+        Token::Megapixels => {
+            if let Ok(megapixels) = item.megapixels() {
+                push_measurement(component, megapixels, &thresholds, |mp| {
+                    format!("{mp:.1}MP")
+                });
+            }
+        }
+        Token::Resolution => {
+            if let Ok(resolution) = item.resolution() {
+                component.push(resolution);
+            }
+        }
+        Token::Duration => {
+            if let Ok(duration) = item.duration() {
+                push_measurement(component, duration, &thresholds, format_duration);
+            }
+        }
+        Token::Bitrate => {
+            if let Ok(bitrate) = item.bitrate() {
+                push_measurement(component, bitrate, &thresholds, |b| {
+                    format!("{}kbps", (b / 1000.).round())
+                });
+            }
+        }
+        Token::Pages => {
+            if let Ok(pages) = item.pages() {
+                push_measurement(component, pages as f64, &thresholds, |p| p.to_string());
+            }
+        }
+        Token::Title => {
+            if let Some(title) = item.document_info().and_then(|i| i.title.as_ref()) {
+                component.push(title);
+            }
+        }
+        Token::Author => {
+            if let Some(author) = item.document_info().and_then(|i| i.author.as_ref()) {
+                component.push(author);
+            }
+        }
+        Token::From => {
+            if let Some(from) = item.email().and_then(|e| e.from.as_ref()) {
+                component.push(from);
+            }
+        }
+        Token::FromDomain => {
+            if let Some(domain) = item.email().and_then(EmailInfo::sender_domain) {
+                component.push(domain);
+            }
+        }
+        Token::To => {
+            if let Some(to) = item.email().and_then(|e| e.to.as_ref()) {
+                component.push(to);
+            }
+        }
+        Token::Subject => {
+            if let Some(subject) = item.email().and_then(|e| e.subject.as_ref()) {
+                component.push(subject);
+            }
+        }
+        Token::Container => {
+            if let Ok(container) = item.container() {
+                component.push(container);
+            }
+        }
+        Token::VideoCodec => {
+            if let Ok(codec) = item.video_codec() {
+                component.push(codec);
+            }
+        }
+        Token::FrameRate => {
+            if let Ok(frame_rate) = item.frame_rate() {
+                push_measurement(component, frame_rate, &thresholds, |fps| {
+                    format!("{}fps", (fps * 100.).round() / 100.)
+                });
+            }
+        }
+        Token::BitDepth => {
+            if let Ok(bit_depth) = item.bit_depth() {
+                push_measurement(component, bit_depth as f64, &thresholds, |bits| {
+                    format!("{bits}bit")
+                });
+            }
+        }
+        Token::Hdr => {
+            if let Ok(hdr) = item.hdr_format() {
+                component.push(hdr);
+            }
+        }
+        Token::AudioLanguages => {
+            if let Ok(languages) = item.audio_languages() {
+                component.push(languages.join("+"));
+            }
+        }
+        Token::SubtitleLanguages => {
+            if let Ok(languages) = item.subtitle_languages() {
+                component.push(languages.join("+"));
+            }
+        }
+        Token::AudioTracks => {
+            if let Ok(tracks) = item.audio_tracks() {
+                push_measurement(component, tracks as f64, &thresholds, |t| t.to_string());
+            }
+        }
+        Token::SubtitleTracks => {
+            if let Ok(tracks) = item.subtitle_tracks() {
+                push_measurement(component, tracks as f64, &thresholds, |t| t.to_string());
+            }
+        }
+        Token::CameraMake => {
+            if let Some(make) = item.photo().and_then(|p| p.camera_make.as_ref()) {
+                component.push(make);
+            }
+        }
+        Token::CameraModel => {
+            if let Some(model) = item.photo().and_then(|p| p.camera_model.as_ref()) {
+                component.push(model);
+            }
+        }
+        Token::Lens => {
+            if let Some(lens) = item.photo().and_then(|p| p.lens.as_ref()) {
+                component.push(lens);
+            }
+        }
+        Token::Iso => {
+            if let Some(iso) = item.photo().and_then(|p| p.iso) {
+                push_measurement(component, iso as f64, &thresholds, |iso| iso.to_string());
+            }
+        }
+        Token::Gps => {
+            if let Some(photo) = item.photo() {
+                component.push(if photo.gps { "gps" } else { "no-gps" });
+            }
+        }
+        Token::Artist => {
+            if let Some(artist) = item.audio_tags().and_then(|t| t.artist.as_ref()) {
+                component.push(artist);
+            }
+        }
+        Token::AlbumArtist => {
+            if let Some(artist) = item.audio_tags().and_then(|t| t.album_artist.as_ref()) {
+                component.push(artist);
+            }
+        }
+        Token::Album => {
+            if let Some(album) = item.audio_tags().and_then(|t| t.album.as_ref()) {
+                component.push(album);
+            }
+        }
+        Token::Track => {
+            if let Some(track) = item.audio_tags().and_then(|t| t.track) {
+                component.push(format!("{track:02}"));
+            }
+        }
+        Token::Disc => {
+            if let Some(disc) = item.audio_tags().and_then(|t| t.disc) {
+                component.push(disc.to_string());
+            }
+        }
+        Token::Genre => {
+            if let Some(genre) = item.audio_tags().and_then(|t| t.genre.as_ref()) {
+                component.push(genre);
+            }
+        }
+        Token::Media => {
+            if let Some(field) = media_field {
+                if let Some(value) = item
+                    .media_report()
+                    .ok()
+                    .and_then(|r| r.field(field.stream, field.index, &field.name))
+                {
+                    component.push(value);
                 }
             }
-            Token::Unknown => {}
         }
+        Token::Owner => {
+            let owner = match specifier {
+                Some(Specifier::Id) => item.owner().map(|uid| uid.to_string()),
+                _ => item.owner_name(),
+            };
+
+            if let Ok(owner) = owner {
+                component.push(owner);
+            }
+        }
+        Token::Group => {
+            let group = match specifier {
+                Some(Specifier::Id) => item.group().map(|gid| gid.to_string()),
+                _ => item.group_name(),
+            };
+
+            if let Ok(group) = group {
+                component.push(group);
+            }
+        }
+        Token::Mode => {
+            if let Ok(mode) = item.mode() {
+                component.push(format!("{mode:04o}"));
+            }
+        }
+        Token::Tag => {
+            let tags = item.tags();
+
+            if !tags.is_empty() {
+                component.push(tags.join("+"));
+            }
+        }
+        Token::Unknown => {}
     }
 
     Ok(())
 }
 
+/// Pushes a measurement, grouped by the variable's thresholds if it has any.
+fn push_measurement<F>(component: &mut Component, value: f64, thresholds: &[Threshold], f: F)
+where
+    F: Fn(f64) -> String,
+{
+    if thresholds.is_empty() {
+        component.push(f(value));
+    } else if let Some(name) = threshold::group(value, thresholds) {
+        component.push(name);
+    }
+}
+
+/// Formats a duration in seconds as e.g. `1h05m`, `4m30s` or `12s`.
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{hours}h{minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
 /// Process the items in the component of the path.
 fn process_items<F>(pattern: &str, item: &mut Item, f: F) -> Result<PathBuf>
 where
-    F: Fn(Pair<Rule>, &mut Vec<String>, &mut Item) -> Result<()> + Copy,
+    F: Fn(Pair<Rule>, &mut Component, &mut Item) -> Result<()> + Copy,
 {
-    let parsed = TokenParser::parse(Rule::path, pattern).expect("failed to parse pattern");
+    let parsed = parse(pattern)?;
     let mut parsed_path = PathBuf::new();
 
     for p in parsed {
         if p.as_rule() == Rule::path {
             for p in p.into_inner() {
                 if p.as_rule() == Rule::component {
-                    let mut component = Vec::new();
+                    let mut component = Component::default();
                     let c = p.into_inner();
                     let count = c.clone().count();
 
@@ -227,12 +771,15 @@ where
                         if p.as_rule() == Rule::variable {
                             f(p, &mut component, item)?;
                         } else if p.as_rule() == Rule::text {
-                            component.push(p.as_str().to_string());
+                            component.push_text(p.as_str());
                         }
                     }
 
-                    if !component.is_empty() && component.len() == count {
-                        parsed_path.push(component.join(""));
+                    // Drop the component if any of its variables didn't produce a value.
+                    if component.values == count {
+                        for segment in component.segments {
+                            parsed_path.push(segment);
+                        }
                     }
                 }
             }
@@ -277,47 +824,287 @@ pub enum Token {
     Month,
     Year,
     Day,
+    Date,
     MimeType,
     Size,
     Extension,
     Width,
     Height,
+    Orientation,
+    Aspect,
+    Megapixels,
+    Resolution,
+    Duration,
+    Pages,
+    Title,
+    Author,
+    From,
+    FromDomain,
+    To,
+    Subject,
+    Bitrate,
+    Container,
+    VideoCodec,
+    FrameRate,
+    BitDepth,
+    Hdr,
+    AudioLanguages,
+    SubtitleLanguages,
+    AudioTracks,
+    SubtitleTracks,
+    CameraMake,
+    CameraModel,
+    Lens,
+    Iso,
+    Gps,
+    Artist,
+    AlbumArtist,
+    Album,
+    Track,
+    Disc,
+    Genre,
+    Media,
     Kind,
+    Tag,
+    Owner,
+    Group,
+    Mode,
+    Count,
+    TrueExtension,
+    Category,
+    ArchiveCount,
+    ArchiveKind,
+    Match,
     Unknown,
 }
 
 impl From<&str> for Token {
     fn from(value: &str) -> Self {
-        match value {
+        match value.to_lowercase().as_str() {
             "year" => Self::Year,
             "month" => Self::Month,
             "day" => Self::Day,
+            "date" => Self::Date,
             "mime" => Self::MimeType,
             "extension" => Self::Extension,
             "size" => Self::Size,
             "kind" => Self::Kind,
             "width" => Self::Width,
             "height" => Self::Height,
+            "orientation" => Self::Orientation,
+            "aspect" => Self::Aspect,
+            "megapixels" => Self::Megapixels,
+            "resolution" => Self::Resolution,
+            "duration" => Self::Duration,
+            "pages" => Self::Pages,
+            "title" => Self::Title,
+            "author" => Self::Author,
+            "from" => Self::From,
+            "from_domain" => Self::FromDomain,
+            "to" => Self::To,
+            "subject" => Self::Subject,
+            "bitrate" => Self::Bitrate,
+            "container" => Self::Container,
+            "video_codec" => Self::VideoCodec,
+            "frame_rate" => Self::FrameRate,
+            "bit_depth" => Self::BitDepth,
+            "hdr" => Self::Hdr,
+            "audio_languages" => Self::AudioLanguages,
+            "subtitle_languages" => Self::SubtitleLanguages,
+            "audio_tracks" => Self::AudioTracks,
+            "subtitle_tracks" => Self::SubtitleTracks,
+            "camera_make" => Self::CameraMake,
+            "camera_model" => Self::CameraModel,
+            "lens" => Self::Lens,
+            "iso" => Self::Iso,
+            "gps" => Self::Gps,
+            "artist" => Self::Artist,
+            "album_artist" => Self::AlbumArtist,
+            "album" => Self::Album,
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            "genre" => Self::Genre,
+            "media" => Self::Media,
+            "tag" => Self::Tag,
+            "owner" => Self::Owner,
+            "group" => Self::Group,
+            "mode" => Self::Mode,
+            "count" => Self::Count,
+            "true_extension" => Self::TrueExtension,
+            "category" => Self::Category,
+            "archive_count" => Self::ArchiveCount,
+            "archive_kind" => Self::ArchiveKind,
+            "match" => Self::Match,
             _ => Self::Unknown,
         }
     }
 }
 
+impl Token {
+    /// The specifiers this token accepts.
+    pub(crate) fn specifiers(&self) -> &'static [Specifier] {
+        match self {
+            Self::Year | Self::Month | Self::Day | Self::Date => &[
+                Specifier::Created,
+                Specifier::Modified,
+                Specifier::Changed,
+                Specifier::Accessed,
+                Specifier::Filename,
+                Specifier::Taken,
+                Specifier::Tag,
+                Specifier::Docdate,
+                Specifier::Sent,
+                Specifier::Newest,
+                Specifier::Oldest,
+            ],
+            Self::MimeType => &[Specifier::Type, Specifier::Subtype],
+            Self::Owner | Self::Group => &[Specifier::Id],
+            _ => &[],
+        }
+    }
+
+    /// The units thresholds can be written in for this token. Tokens without units don't
+    /// accept thresholds.
+    pub(crate) fn units(&self) -> &'static [Unit] {
+        match self {
+            Self::Size => SIZE_UNITS,
+            Self::Width | Self::Height => PIXEL_UNITS,
+            Self::Megapixels => MEGAPIXEL_UNITS,
+            Self::Duration => DURATION_UNITS,
+            Self::Bitrate => BITRATE_UNITS,
+            Self::FrameRate => FRAME_RATE_UNITS,
+            Self::Pages | Self::Iso | Self::BitDepth | Self::Count => COUNT_UNITS,
+            Self::ArchiveCount => COUNT_UNITS,
+            Self::AudioTracks | Self::SubtitleTracks => COUNT_UNITS,
+            _ => &[],
+        }
+    }
+
+    /// Whether this token reads the content of items with the specifier it's used with.
+    pub(crate) fn needs(&self, specifier: Option<&Specifier>) -> Needs {
+        match self {
+            Self::Year | Self::Month | Self::Day | Self::Date => match specifier {
+                Some(Specifier::Taken | Specifier::Tag | Specifier::Docdate | Specifier::Sent) => {
+                    Needs::CONTENT
+                }
+                _ => Needs::NONE,
+            },
+            // File system metadata is read from the item every time.
+            Self::Size | Self::Owner | Self::Group | Self::Mode => Needs::NONE,
+            // Matches come from the Job's conditions, and extended attributes can change
+            // without the file being modified, so tags aren't cached.
+            Self::Extension | Self::Match | Self::Tag | Self::Unknown => Needs::NONE,
+            // Directories are walked when they're used, outside the cache, but the messages
+            // of mbox files are cached.
+            Self::Count
+            | Self::MimeType
+            | Self::Kind
+            | Self::TrueExtension
+            | Self::Category
+            | Self::Pages
+            | Self::Title
+            | Self::Author
+            | Self::From
+            | Self::FromDomain
+            | Self::To
+            | Self::Subject
+            | Self::ArchiveCount
+            | Self::ArchiveKind
+            | Self::CameraMake
+            | Self::CameraModel
+            | Self::Lens
+            | Self::Iso
+            | Self::Gps
+            | Self::Artist
+            | Self::AlbumArtist
+            | Self::Album
+            | Self::Track
+            | Self::Disc
+            | Self::Genre
+            | Self::Width
+            | Self::Height
+            | Self::Orientation
+            | Self::Aspect
+            | Self::Megapixels
+            | Self::Resolution
+            | Self::Duration
+            | Self::Bitrate
+            | Self::Container
+            | Self::VideoCodec
+            | Self::FrameRate
+            | Self::BitDepth
+            | Self::Hdr
+            | Self::AudioLanguages
+            | Self::SubtitleLanguages
+            | Self::AudioTracks
+            | Self::SubtitleTracks
+            | Self::Media => Needs::CONTENT,
+        }
+    }
+
+    /// Whether this token can only be read with MediaInfo. Image dimensions, and the tokens
+    /// built on them, are also read from image headers so they aren't included.
+    pub(crate) fn needs_media_info(&self) -> bool {
+        matches!(
+            self,
+            Self::Resolution
+                | Self::Duration
+                | Self::Bitrate
+                | Self::Container
+                | Self::VideoCodec
+                | Self::FrameRate
+                | Self::BitDepth
+                | Self::Hdr
+                | Self::AudioLanguages
+                | Self::SubtitleLanguages
+                | Self::AudioTracks
+                | Self::SubtitleTracks
+                | Self::Media
+        )
+    }
+
+    /// Checks that the specifier can be used with this token.
+    pub(crate) fn accepts(&self, specifier: &Specifier) -> bool {
+        self.specifiers().contains(specifier)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum Specifier {
     Created,
     Modified,
+    Changed,
     Accessed,
-    _Subpath,
-    _Type,
+    Filename,
+    Taken,
+    Tag,
+    Docdate,
+    Sent,
+    Type,
+    Subtype,
+    Id,
+    Newest,
+    Oldest,
     Unknown,
 }
 
 impl From<&str> for Specifier {
     fn from(value: &str) -> Self {
-        match value {
+        match value.to_lowercase().as_str() {
             "created" => Self::Created,
             "modified" => Self::Modified,
+            "changed" => Self::Changed,
             "accessed" => Self::Accessed,
+            "filename" => Self::Filename,
+            "taken" => Self::Taken,
+            "tag" => Self::Tag,
+            "docdate" => Self::Docdate,
+            "sent" => Self::Sent,
+            "type" => Self::Type,
+            "subtype" => Self::Subtype,
+            "id" => Self::Id,
+            "newest" => Self::Newest,
+            "oldest" => Self::Oldest,
             _ => Self::Unknown,
         }
     }
@@ -340,3 +1127,217 @@ impl From<&str> for Modifier {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::condition::Condition;
+
+    /// The variables and text of a pattern, in order.
+    fn parts(pattern: &str) -> Vec<(Rule, &str)> {
+        parse(pattern)
+            .unwrap()
+            .flatten()
+            .filter(|p| matches!(p.as_rule(), Rule::variable | Rule::text))
+            .map(|p| (p.as_rule(), p.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_only_read_in_braces() {
+        assert_eq!(
+            parts("/Total/{year}"),
+            [(Rule::text, "Total"), (Rule::variable, "{year}")]
+        );
+        assert_eq!(
+            parts("/Tagged/{year}"),
+            [(Rule::text, "Tagged"), (Rule::variable, "{year}")]
+        );
+        assert_eq!(
+            parts("/Media/{year}"),
+            [(Rule::text, "Media"), (Rule::variable, "{year}")]
+        );
+        assert_eq!(
+            parts("/Groups/from {from}"),
+            [
+                (Rule::text, "Groups"),
+                (Rule::text, "from "),
+                (Rule::variable, "{from}")
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_are_only_for_dates() {
+        let variable = |pattern| {
+            let pair = parse(pattern)
+                .unwrap()
+                .flatten()
+                .find(|p| p.as_rule() == Rule::variable)
+                .unwrap();
+
+            Variable::parse(pair)
+        };
+
+        assert!(variable("/{date:created:%Y}").is_ok());
+        assert!(matches!(
+            variable("/{year:created:%Y}"),
+            Err(AppError::InvalidFormat { .. })
+        ));
+        assert!(matches!(
+            variable("/{size:%Y}"),
+            Err(AppError::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn media_fields_pick_a_stream() {
+        let field = |pattern| {
+            let pair = parse(pattern)
+                .unwrap()
+                .flatten()
+                .find(|p| p.as_rule() == Rule::media_field)
+                .unwrap();
+
+            MediaField::parse(pair).unwrap()
+        };
+
+        let extra = field("/{media:Video[1].extra.HDR_Format}");
+        assert_eq!(extra.stream, StreamKind::Video);
+        assert_eq!(extra.index, 1);
+        assert_eq!(extra.name, "extra.HDR_Format");
+
+        let first = field("/{media:General.Format}");
+        assert_eq!(first.stream, StreamKind::General);
+        assert_eq!(first.index, 0);
+    }
+
+    #[test]
+    fn unmatched_braces_are_invalid() {
+        assert!(parse("/{year").is_err());
+        assert!(parse("/{unknown}").is_err());
+        assert!(parse("/{year:created}}").is_err());
+        assert!(parse("/}{year}").is_err());
+        assert!(validate_pattern("/{year:created}}").is_err());
+        assert!(validate_pattern("/{year:created}").is_ok());
+    }
+
+    /// Resolves a pattern for a file modified at noon on March 5th 2024, UTC.
+    fn resolve(pattern: &str) -> PathBuf {
+        resolve_with(pattern, "", &[])
+    }
+
+    /// Resolves the pattern for a text file once the conditions of its Job hold.
+    fn resolve_with(pattern: &str, text: &str, conditions: &[Condition]) -> PathBuf {
+        use crate::{
+            dates::Timezone,
+            options::{Context, Options},
+        };
+        use chrono::{TimeZone, Utc};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Tests run at the same time, so each one has its own file.
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!("hazel-parser-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.txt", FILES.fetch_add(1, Ordering::Relaxed)));
+        std::fs::write(&path, text).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        let modified = Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap();
+        file.set_modified(modified.into()).unwrap();
+
+        let entry = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .find(|entry| entry.path() == path)
+            .unwrap();
+        let mut context = Context::new(&Options::default()).unwrap();
+        context.timezone = Timezone::new("utc").unwrap();
+        let mut item = Item::new(&entry, &context).unwrap();
+
+        for condition in conditions {
+            assert!(condition.holds(&mut item), "{condition:?}");
+        }
+
+        let resolved = parse_pattern(pattern, &mut item).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        resolved
+    }
+
+    #[test]
+    fn date_formats_make_several_levels() {
+        assert_eq!(resolve("/{date:modified:%Y/%m}"), PathBuf::from("2024/03"));
+        assert_eq!(
+            resolve("/Photos {date:modified:%Y/%m/%d}"),
+            PathBuf::from("Photos 2024/03/05")
+        );
+        // Empty directories from a `//` or a trailing `/` are left out.
+        assert_eq!(
+            resolve("/{date:modified:%Y//%m/}"),
+            PathBuf::from("2024/03")
+        );
+    }
+
+    #[test]
+    fn components_without_a_value_are_dropped() {
+        assert_eq!(
+            resolve("/{tag}/kept/{tag} too/{year:modified}"),
+            PathBuf::from("kept/2024")
+        );
+        assert_eq!(resolve("/{tag}"), PathBuf::new());
+    }
+
+    #[test]
+    fn match_groups_come_from_the_contains_condition() {
+        let search = serde_yaml::from_str(r"regex: 'Invoice (INV-\d+)(?: for (\w+))?'").unwrap();
+        let conditions = [Condition::Contains(search)];
+
+        assert_eq!(
+            resolve_with("/{match:1}/{match}", "Invoice INV-42 due", &conditions),
+            PathBuf::from("INV-42/Invoice INV-42")
+        );
+        // Groups that took no part in the match, or aren't in the regex, are dropped.
+        assert_eq!(
+            resolve_with(
+                "/{match:1}/{match:2}/{match:3}",
+                "Invoice INV-7 for ACME",
+                &conditions
+            ),
+            PathBuf::from("INV-7/ACME")
+        );
+        assert_eq!(
+            resolve_with("/{match:1}/{match:2}", "Invoice INV-7", &conditions),
+            PathBuf::from("INV-7")
+        );
+    }
+
+    #[test]
+    fn values_are_single_directories() {
+        let mut component = Component::default();
+        component.push_text("in ");
+        component.push("a/b\\c");
+        assert_eq!(component.segments, ["in a-b-c"]);
+        assert_eq!(component.values, 2);
+
+        component.push_segments(["x", "y"]);
+        assert_eq!(component.segments, ["in a-b-cx", "y"]);
+        assert_eq!(component.values, 3);
+    }
+
+    #[test]
+    fn dot_values_are_dropped() {
+        let mut component = Component::default();
+
+        for value in [".", "..", " .. ", "", "  "] {
+            component.push(value);
+        }
+        component.push_segments(["2024", ".."]);
+
+        assert!(component.segments.is_empty());
+        assert_eq!(component.values, 0);
+        assert_eq!(sanitize_segment("..."), "...");
+        assert_eq!(sanitize_segment("a\tb"), "a_b");
+    }
+}
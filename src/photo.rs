@@ -0,0 +1,73 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{DateTime, Exif, In, Reader, Tag, Value};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, path::Path};
+
+/// The EXIF metadata of a photo that can be used in a pattern.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct PhotoInfo {
+    pub(crate) camera_make: Option<String>,
+    pub(crate) camera_model: Option<String>,
+    pub(crate) lens: Option<String>,
+    pub(crate) iso: Option<u32>,
+    pub(crate) gps: bool,
+    pub(crate) taken: Option<NaiveDateTime>,
+    /// Whether the photo is shown turned a quarter, so it's as wide as it's stored high. That's
+    /// EXIF orientations 5 to 8.
+    #[serde(default)]
+    pub(crate) rotated: bool,
+}
+
+impl PhotoInfo {
+    /// Reads the EXIF metadata from JPEG, HEIF/HEIC, PNG, WebP, TIFF and the RAW formats
+    /// based on TIFF (e.g. CR2, NEF, ARW and DNG).
+    pub(crate) fn read(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .ok()?;
+
+        Some(Self {
+            camera_make: ascii(&exif, Tag::Make),
+            camera_model: ascii(&exif, Tag::Model),
+            lens: ascii(&exif, Tag::LensModel),
+            iso: exif
+                .get_field(Tag::PhotographicSensitivity, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0)),
+            gps: exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some(),
+            taken: [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+                .into_iter()
+                .find_map(|tag| date(&exif, tag)),
+            rotated: exif
+                .get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+                .is_some_and(|orientation| (5..=8).contains(&orientation)),
+        })
+    }
+}
+
+/// Reads a text field, ignoring the padding some cameras add.
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => {
+            let value = String::from_utf8_lossy(values.first()?);
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn date(exif: &Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let value = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+
+    NaiveDate::from_ymd_opt(value.year.into(), value.month.into(), value.day.into())?.and_hms_opt(
+        value.hour.into(),
+        value.minute.into(),
+        value.second.into(),
+    )
+}
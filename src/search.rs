@@ -0,0 +1,164 @@
+use crate::{document::Limit, error::AppError};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// What a `contains` condition looks for in the text of a file.
+///
+/// ```yaml
+/// conditions:
+///   - contains:
+///       regex: 'Invoice (INV-\d+)'
+///       pages: 1
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+#[serde(default)]
+pub(crate) struct SearchOptions {
+    /// Text to find.
+    text: Option<String>,
+    /// A regex to find, whose groups can be used as `{match:1}`.
+    regex: Option<String>,
+    ignore_case: bool,
+    /// The pages of a PDF searched from the start.
+    pages: Option<usize>,
+    /// The bytes of text searched from the start.
+    bytes: Option<usize>,
+}
+
+/// A search, with its text or regex compiled when the jobs are loaded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "SearchOptions", into = "SearchOptions")]
+pub(crate) struct Search {
+    options: SearchOptions,
+    regex: Regex,
+}
+
+impl TryFrom<SearchOptions> for Search {
+    type Error = AppError;
+
+    fn try_from(options: SearchOptions) -> Result<Self, Self::Error> {
+        let pattern = match (&options.text, &options.regex) {
+            (Some(text), None) => regex::escape(text),
+            (None, Some(regex)) => regex.to_string(),
+            _ => return Err(AppError::InvalidSearch),
+        };
+
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case)
+            .build()?;
+
+        Ok(Self { options, regex })
+    }
+}
+
+impl From<Search> for SearchOptions {
+    fn from(search: Search) -> Self {
+        search.options
+    }
+}
+
+impl PartialEq for Search {
+    fn eq(&self, other: &Self) -> bool {
+        self.options == other.options
+    }
+}
+
+impl Eq for Search {}
+
+impl Search {
+    pub(crate) fn limit(&self) -> Limit {
+        Limit {
+            pages: self.options.pages,
+            bytes: self.options.bytes,
+        }
+    }
+
+    /// Finds the first match in the text, with the text of each group. The whole match is
+    /// group 0.
+    pub(crate) fn find(&self, text: &str) -> Option<Vec<Option<String>>> {
+        let captures = self.regex.captures(text)?;
+
+        Some(
+            captures
+                .iter()
+                .map(|group| group.map(|group| group.as_str().to_string()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(yaml: &str) -> Search {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn text_is_found_literally() {
+        let search = search("text: 'Total (EUR)'");
+
+        assert_eq!(
+            search.find("Invoice Total (EUR): 12"),
+            Some(vec![Some("Total (EUR)".to_string())])
+        );
+        assert_eq!(search.find("Total EUR"), None);
+        assert_eq!(search.find("total (eur)"), None);
+    }
+
+    #[test]
+    fn case_can_be_ignored() {
+        let search = search("{ text: invoice, ignore_case: true }");
+
+        assert_eq!(
+            search.find("INVOICE 3"),
+            Some(vec![Some("INVOICE".to_string())])
+        );
+    }
+
+    #[test]
+    fn regex_groups_are_kept() {
+        let search = search(r"regex: 'Invoice (INV-\d+)(?: for (\w+))?'");
+
+        assert_eq!(
+            search.find("Re: Invoice INV-12 for ACME, Invoice INV-13"),
+            Some(vec![
+                Some("Invoice INV-12 for ACME".to_string()),
+                Some("INV-12".to_string()),
+                Some("ACME".to_string()),
+            ])
+        );
+        assert_eq!(
+            search.find("Invoice INV-13"),
+            Some(vec![
+                Some("Invoice INV-13".to_string()),
+                Some("INV-13".to_string()),
+                None
+            ])
+        );
+        assert_eq!(search.find("Invoice INV-"), None);
+    }
+
+    #[test]
+    fn searches_need_text_or_a_regex() {
+        for yaml in ["{}", "{ text: a, regex: b }"] {
+            assert!(
+                matches!(
+                    serde_yaml::from_str::<Search>(yaml),
+                    Err(e) if e.to_string().contains("needs either")
+                ),
+                "{yaml}"
+            );
+        }
+
+        assert!(serde_yaml::from_str::<Search>("regex: '(unclosed'").is_err());
+    }
+
+    #[test]
+    fn limits_are_kept() {
+        let limit = search("{ text: a, pages: 1, bytes: 100 }").limit();
+
+        assert_eq!(limit.pages, Some(1));
+        assert_eq!(limit.bytes, Some(100));
+    }
+}
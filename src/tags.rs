@@ -0,0 +1,139 @@
+use crate::{error::AppError, Result};
+use serde::{Deserialize, Deserializer};
+use std::{io, path::Path};
+
+/// The extended attribute desktop environments keep file tags in, as a comma-separated list.
+pub(crate) const TAGS_ATTRIBUTE: &str = "user.xdg.tags";
+
+/// Reads the tags of a file, in the order they were added.
+pub(crate) fn read(path: &Path) -> Vec<String> {
+    match xattr::get(path, TAGS_ATTRIBUTE) {
+        Ok(Some(value)) => parse(&value),
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces the tags of a file, removing the attribute when there are none left.
+pub(crate) fn write(path: &Path, tags: &[String]) -> io::Result<()> {
+    if tags.is_empty() {
+        match xattr::remove(path, TAGS_ATTRIBUTE) {
+            // The attribute wasn't there in the first place.
+            Err(e) if e.raw_os_error() == Some(libc::ENODATA) => Ok(()),
+            result => result,
+        }
+    } else {
+        xattr::set(path, TAGS_ATTRIBUTE, tags.join(",").as_bytes())
+    }
+}
+
+/// Splits the value of the attribute into tags, leaving out the spaces around them, empty
+/// tags and repeated ones.
+fn parse(value: &[u8]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in String::from_utf8_lossy(value).split(',').map(str::trim) {
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+
+    tags
+}
+
+/// Adds a tag at the end, returning whether it wasn't there yet.
+pub(crate) fn add(tags: &mut Vec<String>, tag: &str) -> bool {
+    if tags.iter().any(|t| t == tag) {
+        return false;
+    }
+
+    tags.push(tag.to_string());
+    true
+}
+
+/// Removes a tag, returning whether it was there.
+pub(crate) fn remove(tags: &mut Vec<String>, tag: &str) -> bool {
+    let before = tags.len();
+    tags.retain(|t| t != tag);
+
+    tags.len() != before
+}
+
+/// Reads a tag from a Job, without the spaces around it, as they're dropped when tags are read.
+pub(crate) fn trimmed<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    Ok(String::deserialize(deserializer)?.trim().to_string())
+}
+
+/// Checks a tag can be stored in the comma-separated list.
+pub(crate) fn validate(tag: &str) -> Result<()> {
+    if tag.is_empty() || tag.contains(',') {
+        return Err(AppError::InvalidTag {
+            tag: tag.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks if a file has a `user.*` extended attribute.
+pub(crate) fn has_attribute(path: &Path, name: &str) -> bool {
+    matches!(xattr::get(path, name), Ok(Some(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::Action, condition::Condition};
+
+    #[test]
+    fn lists_are_split_at_commas() {
+        assert_eq!(parse(b"inbox,work"), ["inbox", "work"]);
+        assert_eq!(
+            parse(b" inbox , to do ,,work, "),
+            ["inbox", "to do", "work"]
+        );
+        assert_eq!(parse(b"inbox,work,inbox"), ["inbox", "work"]);
+        assert!(parse(b"").is_empty());
+        assert!(parse(b" , ").is_empty());
+    }
+
+    #[test]
+    fn tags_are_added_and_removed_once() {
+        let mut tags = parse(b"inbox,work");
+
+        assert!(add(&mut tags, "done"));
+        assert!(!add(&mut tags, "inbox"));
+        assert_eq!(parse(tags.join(",").as_bytes()), ["inbox", "work", "done"]);
+
+        assert!(remove(&mut tags, "inbox"));
+        assert!(!remove(&mut tags, "inbox"));
+        assert_eq!(parse(tags.join(",").as_bytes()), ["work", "done"]);
+
+        assert!(remove(&mut tags, "work"));
+        assert!(remove(&mut tags, "done"));
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn tags_in_jobs_are_trimmed() {
+        let condition: Condition = serde_yaml::from_str("!tagged '  inbox '").unwrap();
+        let action: Action = serde_yaml::from_str("!add_tag ' done'").unwrap();
+
+        assert_eq!(condition, Condition::Tagged("inbox".to_string()));
+        assert_eq!(action, Action::AddTag("done".to_string()));
+    }
+
+    #[test]
+    fn tags_without_a_name_or_with_a_comma_are_rejected() {
+        assert!(validate("inbox").is_ok());
+        assert!(validate("to do").is_ok());
+
+        for tag in ["", "a,b", ","] {
+            assert!(
+                matches!(validate(tag), Err(AppError::InvalidTag { .. })),
+                "{tag}"
+            );
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::parser::Rule;
+use pest::iterators::Pair;
+
+/// A unit a threshold amount can be written in, and how many base units it's worth.
+pub(crate) type Unit = (&'static str, f64);
+
+/// Bytes, in binary multiples.
+pub(crate) const SIZE_UNITS: &[Unit] = &[
+    ("B", 1.),
+    ("K", 1024.),
+    ("M", 1024. * 1024.),
+    ("G", 1024. * 1024. * 1024.),
+    ("T", 1024. * 1024. * 1024. * 1024.),
+];
+
+/// Pixels.
+pub(crate) const PIXEL_UNITS: &[Unit] = &[("", 1.), ("px", 1.)];
+
+/// Millions of pixels.
+pub(crate) const MEGAPIXEL_UNITS: &[Unit] = &[("", 1.), ("MP", 1.)];
+
+/// Seconds.
+pub(crate) const DURATION_UNITS: &[Unit] = &[
+    ("", 1.),
+    ("ms", 0.001),
+    ("s", 1.),
+    ("m", 60.),
+    ("h", 60. * 60.),
+];
+
+/// Frames per second.
+pub(crate) const FRAME_RATE_UNITS: &[Unit] = &[("", 1.), ("fps", 1.)];
+
+/// Bits per second.
+pub(crate) const BITRATE_UNITS: &[Unit] = &[
+    ("", 1.),
+    ("bps", 1.),
+    ("K", 1000.),
+    ("k", 1000.),
+    ("kbps", 1000.),
+    ("M", 1000. * 1000.),
+    ("Mbps", 1000. * 1000.),
+];
+
+/// A plain count, e.g. pages.
+pub(crate) const COUNT_UNITS: &[Unit] = &[("", 1.)];
+
+/// Whether a value has to be over or under the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Bound {
+    Over,
+    Under,
+}
+
+/// A single threshold in a variable, e.g. the `>500M` in `{size[>500M]}`.
+#[derive(Debug, Clone)]
+pub(crate) struct Threshold {
+    name: String,
+    bound: Bound,
+    amount: f64,
+}
+
+impl Threshold {
+    /// Reads a threshold from the pattern, converting its amount with the token's units.
+    /// Returns `None` if the amount is written in a unit the token doesn't have.
+    pub(crate) fn parse(threshold: Pair<Rule>, units: &[Unit]) -> Option<Self> {
+        let name = threshold.as_str().to_string();
+        let mut bound = Bound::Over;
+        let mut amount = 0.;
+        let mut unit = "";
+
+        for t in threshold.into_inner() {
+            match t.as_rule() {
+                Rule::gt => bound = Bound::Over,
+                Rule::lt => bound = Bound::Under,
+                Rule::threshold_amount => amount = t.as_str().parse().ok()?,
+                Rule::threshold_unit => unit = t.as_str(),
+                _ => {}
+            }
+        }
+
+        let (_, multiplier) = units.iter().find(|(u, _)| *u == unit)?;
+
+        Some(Self {
+            name,
+            bound,
+            amount: amount * multiplier,
+        })
+    }
+
+    /// How far the value is past this threshold, if it's on the right side of it.
+    fn distance(&self, value: f64) -> Option<f64> {
+        match self.bound {
+            Bound::Over if value >= self.amount => Some(value - self.amount),
+            Bound::Under if value < self.amount => Some(self.amount - value),
+            _ => None,
+        }
+    }
+}
+
+/// Finds the threshold a value belongs to.
+///
+/// When the value is on the right side of several thresholds the closest one wins, so
+/// `[>30,>60,>90]` puts 75 into `>60` and `[<1m,<10m]` puts 30s into `<1m`. Values that don't
+/// pass any threshold aren't grouped.
+pub(crate) fn group(value: f64, thresholds: &[Threshold]) -> Option<&str> {
+    let mut closest: Option<(&Threshold, f64)> = None;
+
+    for threshold in thresholds {
+        if let Some(distance) = threshold.distance(value) {
+            if closest.is_none_or(|(_, d)| distance < d) {
+                closest = Some((threshold, distance));
+            }
+        }
+    }
+
+    closest.map(|(threshold, _)| threshold.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::TokenParser;
+    use pest::Parser;
+
+    fn threshold(text: &str, units: &[Unit]) -> Option<Threshold> {
+        let pair = TokenParser::parse(Rule::threshold, text).unwrap().next()?;
+
+        Threshold::parse(pair, units)
+    }
+
+    fn amount(text: &str, units: &[Unit]) -> Option<f64> {
+        threshold(text, units).map(|threshold| threshold.amount)
+    }
+
+    fn thresholds(texts: &[&str], units: &[Unit]) -> Vec<Threshold> {
+        texts.iter().map(|t| threshold(t, units).unwrap()).collect()
+    }
+
+    #[test]
+    fn amounts_are_in_base_units() {
+        assert_eq!(amount(">500B", SIZE_UNITS), Some(500.));
+        assert_eq!(amount(">2K", SIZE_UNITS), Some(2048.));
+        assert_eq!(amount(">1.5M", SIZE_UNITS), Some(1.5 * 1024. * 1024.));
+        assert_eq!(amount(">1G", SIZE_UNITS), Some(1024. * 1024. * 1024.));
+        assert_eq!(amount(">12MP", MEGAPIXEL_UNITS), Some(12.));
+        assert_eq!(amount(">12", MEGAPIXEL_UNITS), Some(12.));
+        assert_eq!(amount(">1920px", PIXEL_UNITS), Some(1920.));
+        assert_eq!(amount("<500ms", DURATION_UNITS), Some(0.5));
+        assert_eq!(amount("<30s", DURATION_UNITS), Some(30.));
+        assert_eq!(amount("<10m", DURATION_UNITS), Some(600.));
+        assert_eq!(amount(">2h", DURATION_UNITS), Some(7200.));
+        assert_eq!(amount(">90", DURATION_UNITS), Some(90.));
+        assert_eq!(amount(">320kbps", BITRATE_UNITS), Some(320_000.));
+        assert_eq!(amount(">320k", BITRATE_UNITS), Some(320_000.));
+        assert_eq!(amount(">8Mbps", BITRATE_UNITS), Some(8_000_000.));
+        assert_eq!(amount(">30fps", FRAME_RATE_UNITS), Some(30.));
+        assert_eq!(amount(">10", COUNT_UNITS), Some(10.));
+    }
+
+    #[test]
+    fn unknown_and_missing_units_are_rejected() {
+        assert!(threshold(">5X", SIZE_UNITS).is_none());
+        assert!(threshold(">5fps", DURATION_UNITS).is_none());
+        assert!(threshold(">5MP", COUNT_UNITS).is_none());
+        // Sizes have no unit without a name, as bytes are written `B`.
+        assert!(threshold(">500", SIZE_UNITS).is_none());
+    }
+
+    #[test]
+    fn the_closest_passing_threshold_wins() {
+        let over = thresholds(&[">30", ">60", ">90"], COUNT_UNITS);
+        assert_eq!(group(75., &over), Some(">60"));
+        assert_eq!(group(90., &over), Some(">90"));
+        assert_eq!(group(30., &over), Some(">30"));
+
+        let under = thresholds(&["<1m", "<10m"], DURATION_UNITS);
+        assert_eq!(group(30., &under), Some("<1m"));
+        assert_eq!(group(300., &under), Some("<10m"));
+
+        let mixed = thresholds(&["<10", ">100", ">20"], COUNT_UNITS);
+        assert_eq!(group(5., &mixed), Some("<10"));
+        assert_eq!(group(50., &mixed), Some(">20"));
+        assert_eq!(group(150., &mixed), Some(">100"));
+        // 9 is 1 under `<10`, closer than anything else.
+        let close = thresholds(&[">5", "<10"], COUNT_UNITS);
+        assert_eq!(group(9., &close), Some("<10"));
+        assert_eq!(group(6., &close), Some(">5"));
+    }
+
+    #[test]
+    fn values_that_pass_no_threshold_are_not_grouped() {
+        let thresholds = thresholds(&[">30", "<10"], COUNT_UNITS);
+
+        assert_eq!(group(10., &thresholds), None);
+        assert_eq!(group(20., &thresholds), None);
+        assert_eq!(group(20., &[]), None);
+    }
+}
@@ -0,0 +1,132 @@
+use libc::{c_char, c_int, gid_t, group, passwd, uid_t};
+use std::{cell::RefCell, collections::HashMap, ffi::CStr, mem, ptr};
+
+thread_local! {
+    // Items in a folder usually share a few owners, so names are only looked up once.
+    static USERS: RefCell<HashMap<uid_t, Option<String>>> = RefCell::new(HashMap::new());
+    static GROUPS: RefCell<HashMap<gid_t, Option<String>>> = RefCell::new(HashMap::new());
+}
+
+/// Buffer size for the strings of a passwd or group entry when sysconf doesn't suggest one.
+const BUFFER_SIZE: usize = 1024;
+const MAX_BUFFER_SIZE: usize = 1 << 20;
+
+/// The name of a user, or `None` when the uid has no entry, e.g. files from another machine.
+pub(crate) fn user_name(uid: uid_t) -> Option<String> {
+    USERS.with(|users| {
+        users
+            .borrow_mut()
+            .entry(uid)
+            .or_insert_with(|| {
+                lookup(
+                    libc::_SC_GETPW_R_SIZE_MAX,
+                    |entry: &mut passwd, buf, result| unsafe {
+                        libc::getpwuid_r(uid, entry, buf.as_mut_ptr(), buf.len(), result)
+                    },
+                )
+            })
+            .clone()
+    })
+}
+
+/// The name of a group, or `None` when the gid has no entry.
+pub(crate) fn group_name(gid: gid_t) -> Option<String> {
+    GROUPS.with(|groups| {
+        groups
+            .borrow_mut()
+            .entry(gid)
+            .or_insert_with(|| {
+                lookup(
+                    libc::_SC_GETGR_R_SIZE_MAX,
+                    |entry: &mut group, buf, result| unsafe {
+                        libc::getgrgid_r(gid, entry, buf.as_mut_ptr(), buf.len(), result)
+                    },
+                )
+            })
+            .clone()
+    })
+}
+
+/// An entry filled in by getpwuid_r or getgrgid_r.
+///
+/// # Safety
+///
+/// Only for plain C structs made of integers and pointers, for which all zeroes is a valid
+/// value.
+unsafe trait Entry {
+    /// The name, pointing into the buffer the entry was filled in with.
+    fn name(&self) -> *const c_char;
+}
+
+unsafe impl Entry for passwd {
+    fn name(&self) -> *const c_char {
+        self.pw_name
+    }
+}
+
+unsafe impl Entry for group {
+    fn name(&self) -> *const c_char {
+        self.gr_name
+    }
+}
+
+/// Calls one of the reentrant getpw/getgr functions, growing the buffer while it's too small,
+/// and reads the name from the entry while the buffer its strings point into is alive.
+fn lookup<T: Entry>(
+    size_hint: c_int,
+    get: impl Fn(&mut T, &mut [c_char], *mut *mut T) -> c_int,
+) -> Option<String> {
+    let hint = unsafe { libc::sysconf(size_hint) };
+    let mut buf: Vec<c_char> = vec![0; usize::try_from(hint).unwrap_or(0).max(BUFFER_SIZE)];
+
+    loop {
+        // SAFETY: `Entry` is only implemented for passwd and group, C structs of integers and
+        // pointers for which all zeroes is valid. The pointers are null until `get` fills them
+        // in, and aren't read unless it succeeds.
+        let mut entry: T = unsafe { mem::zeroed() };
+        let mut result: *mut T = ptr::null_mut();
+        let code = get(&mut entry, &mut buf, &mut result);
+
+        if code == libc::ERANGE && buf.len() < MAX_BUFFER_SIZE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+
+        if code != 0 || result.is_null() {
+            return None;
+        }
+
+        let name = entry.name();
+
+        if name.is_null() {
+            return None;
+        }
+
+        // SAFETY: the name is a NUL-terminated string in `buf`, which outlives this borrow.
+        return Some(
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_has_a_name() {
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+        assert!(group_name(0).is_some());
+    }
+
+    #[test]
+    fn unknown_ids_have_no_name() {
+        // Far above the ids handed out by useradd and below the (uid_t)-1 used for "no change".
+        let id = 4_000_000_000;
+
+        assert_eq!(user_name(id), None);
+        assert_eq!(group_name(id), None);
+    }
+}
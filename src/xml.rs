@@ -0,0 +1,200 @@
+use quick_xml::{
+    escape::resolve_predefined_entity,
+    events::{BytesStart, Event},
+    Reader,
+};
+
+/// Reads the text of an XML document, with a line break after each element whose local name
+/// is in `lines`, e.g. the `w:p` paragraphs and `w:br` breaks of DOCX. The `w:tab` and
+/// `text:tab` tabs and `text:s` spaces of DOCX and ODF are written as whitespace.
+pub(crate) fn text(xml: &str, lines: &[&str]) -> String {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let is_line = |name: &[u8]| lines.iter().any(|line| line.as_bytes() == name);
+    // DOCX also has `w:tab` elements in the `w:tabs` stops of a paragraph's style.
+    let mut in_tabs = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.local_name().as_ref() == b"tabs" => in_tabs = true,
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"tabs" => in_tabs = false,
+            Ok(Event::Start(e)) => text.push_str(&whitespace(&e, in_tabs)),
+            Ok(Event::Empty(e)) if is_line(e.local_name().as_ref()) => text.push('\n'),
+            Ok(Event::Empty(e)) => text.push_str(&whitespace(&e, in_tabs)),
+            Ok(Event::Text(t)) => {
+                if let Ok(t) = t.decode() {
+                    text.push_str(&t);
+                }
+            }
+            Ok(Event::CData(t)) => {
+                if let Ok(t) = t.decode() {
+                    text.push_str(&t);
+                }
+            }
+            Ok(Event::GeneralRef(r)) => {
+                if let Some(c) = entity(&r) {
+                    text.push_str(&c);
+                }
+            }
+            Ok(Event::End(e)) if is_line(e.local_name().as_ref()) => text.push('\n'),
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// The whitespace an element stands for, which is only the `tab` tabs outside of `tabs` and
+/// the `s` spaces, where `text:c` is the number of spaces.
+fn whitespace(element: &BytesStart, in_tabs: bool) -> String {
+    match element.local_name().as_ref() {
+        b"tab" if !in_tabs => "\t".to_string(),
+        b"s" => {
+            let count = element
+                .attributes()
+                .flatten()
+                .find(|a| a.key.local_name().as_ref() == b"c")
+                .and_then(|a| std::str::from_utf8(&a.value).ok()?.parse().ok())
+                .unwrap_or(1);
+
+            " ".repeat(count)
+        }
+        _ => String::new(),
+    }
+}
+
+/// Resolves a character reference or one of the entities XML predefines.
+fn entity(reference: &quick_xml::events::BytesRef) -> Option<String> {
+    if let Ok(Some(c)) = reference.resolve_char_ref() {
+        return Some(c.to_string());
+    }
+
+    let name = reference.decode().ok()?;
+
+    resolve_predefined_entity(&name).map(str::to_string)
+}
+
+/// Reads the text of the first element with each of the local names, e.g. `title` for
+/// `dc:title`. When the element holds a list, like the `rdf:li` creators of XMP, only the first
+/// item is read.
+pub(crate) fn first_texts<const N: usize>(xml: &str, names: [&str; N]) -> [Option<String>; N] {
+    let mut reader = Reader::from_str(xml);
+    let mut texts = [const { None }; N];
+    let mut depth = 0;
+    // The element being read, the depth it's at, the depth its text is at and the text.
+    let mut current: Option<(usize, usize, Option<usize>, String)> = None;
+
+    loop {
+        let text = match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                depth += 1;
+
+                if current.is_none() {
+                    let index = names
+                        .iter()
+                        .position(|name| name.as_bytes() == e.local_name().as_ref());
+
+                    if let Some(index) = index.filter(|&index| texts[index].is_none()) {
+                        current = Some((index, depth, None, String::new()));
+                    }
+                }
+
+                continue;
+            }
+            Ok(Event::End(_)) => {
+                if let Some((index, start, text_depth, text)) = &current {
+                    if depth == *start || Some(depth) == *text_depth {
+                        let text = text.trim();
+
+                        texts[*index] = (!text.is_empty()).then(|| text.to_string());
+                        current = None;
+                    }
+                }
+
+                depth -= 1;
+                continue;
+            }
+            Ok(Event::Text(t)) => t.decode().ok().map(|t| t.into_owned()),
+            Ok(Event::CData(t)) => t.decode().ok().map(|t| t.into_owned()),
+            Ok(Event::GeneralRef(r)) => entity(&r),
+            Ok(Event::Eof) | Err(_) => break,
+            _ => continue,
+        };
+
+        if let (Some((_, _, text_depth, buffer)), Some(text)) = (&mut current, text) {
+            // The text starts at the first element that has more than whitespace.
+            if text_depth.is_none() && !text.trim().is_empty() {
+                *text_depth = Some(depth);
+            }
+
+            if *text_depth == Some(depth) {
+                buffer.push_str(&text);
+            }
+        }
+    }
+
+    texts
+}
+
+/// Reads an attribute of the first element with the local name that has it, e.g.
+/// `meta:page-count` of `meta:document-statistic` as `("document-statistic", "page-count")`.
+pub(crate) fn attribute(xml: &str, element: &str, name: &str) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e))
+                if e.local_name().as_ref() == element.as_bytes() =>
+            {
+                let value = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.local_name().as_ref() == name.as_bytes())
+                    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()));
+
+                if value.is_some() {
+                    return value;
+                }
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabs_and_spaces_are_whitespace() {
+        let docx = r#"<w:document><w:body><w:p><w:pPr><w:tabs><w:tab w:val="left" w:pos="720"/></w:tabs></w:pPr><w:r><w:t>Invoice</w:t><w:tab/><w:t>INV-1</w:t></w:r></w:p><w:p><w:r><w:t>Total</w:t></w:r></w:p></w:body></w:document>"#;
+        let odt = r#"<office:text><text:p>Invoice<text:tab/>INV-1<text:s text:c="3"/>paid<text:s/>now</text:p></office:text>"#;
+
+        assert_eq!(text(docx, &["p", "br"]), "Invoice\tINV-1\nTotal\n");
+        assert_eq!(text(odt, &["p"]), "Invoice\tINV-1   paid now\n");
+    }
+
+    #[test]
+    fn first_texts_that_arent_empty() {
+        let xml = r#"<meta><dc:title>  </dc:title><dc:title>Report</dc:title><dc:creator><rdf:Seq><rdf:li>Ada</rdf:li><rdf:li>Charles</rdf:li></rdf:Seq></dc:creator></meta>"#;
+
+        assert_eq!(
+            first_texts(xml, ["creator", "title", "date"]),
+            [Some("Ada".to_string()), Some("Report".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn attributes_of_the_first_element_with_them() {
+        let container = r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+
+        assert_eq!(
+            attribute(container, "rootfile", "full-path").as_deref(),
+            Some("OEBPS/content.opf")
+        );
+        assert_eq!(attribute(container, "rootfile", "version"), None);
+        assert_eq!(attribute(container, "package", "full-path"), None);
+    }
+}